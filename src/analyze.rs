@@ -0,0 +1,352 @@
+use {
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        fmt::Write,
+    },
+
+    mpegts::psi,
+
+    crate::{
+        section::SectionReader,
+        dump,
+    },
+};
+
+
+/// PCR wraps around after 2^33 ticks of 90 kHz clock
+const PCR_WRAP: u64 = 1 << 33;
+const PCR_RATE: f64 = 90_000.0;
+
+
+#[inline]
+fn get_u16(data: &[u8]) -> u16 {
+    (u16::from(data[0]) << 8) | u16::from(data[1])
+}
+
+
+/// Returns PCR base if packet has it
+fn ts_pcr(packet: &[u8]) -> Option<u64> {
+    if packet[3] & 0x20 == 0 || packet[4] < 7 || packet[5] & 0x10 == 0 {
+        return None;
+    }
+
+    let pcr = &packet[6 .. 11];
+    Some(
+        (u64::from(pcr[0]) << 25) |
+        (u64::from(pcr[1]) << 17) |
+        (u64::from(pcr[2]) << 9) |
+        (u64::from(pcr[3]) << 1) |
+        (u64::from(pcr[4]) >> 7)
+    )
+}
+
+
+/// Minimum, maximum, and average of the measured values
+#[derive(Debug, Default)]
+struct Range {
+    count: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+
+impl Range {
+    fn push(&mut self, value: f64) {
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn avg(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.sum / self.count as f64 }
+    }
+}
+
+
+/// Statistics of the one table_id
+#[derive(Debug, Default)]
+struct TableStat {
+    sections: u64,
+    version_changes: u64,
+    /// Interval between repetitions of the same section in seconds
+    interval: Range,
+}
+
+
+/// Statistics of the one PID
+#[derive(Debug, Default)]
+struct PidStat {
+    reader: SectionReader,
+    packets: u64,
+    cc_errors: u64,
+    last_cc: Option<u8>,
+}
+
+
+impl PidStat {
+    fn check_cc(&mut self, packet: &[u8]) {
+        // no payload. counter not incremented
+        if packet[3] & 0x10 == 0 {
+            return;
+        }
+
+        // discontinuity_indicator in the adaptation field. counter could be changed
+        let is_discontinuity = packet[3] & 0x20 != 0 && packet[4] > 0 && packet[5] & 0x80 != 0;
+
+        let cc = packet[3] & 0x0F;
+        if let Some(last_cc) = self.last_cc.filter(|_| ! is_discontinuity) {
+            // single duplicate packet is allowed
+            if cc != (last_cc + 1) & 0x0F && cc != last_cc {
+                self.cc_errors += 1;
+            }
+        }
+        self.last_cc = Some(cc);
+    }
+}
+
+
+/// Analyzes EIT and TDT/TOT in the transport stream
+#[derive(Debug, Default)]
+pub struct Analyzer {
+    packets: u64,
+    pid_map: BTreeMap<u16, PidStat>,
+    table_map: BTreeMap<u8, TableStat>,
+    /// Last time of the section: table_id, service_id, tsid, onid, section_number
+    section_map: HashMap<(u8, u16, u16, u16, u8), f64>,
+    /// Last version of the sub-table: table_id, service_id, tsid, onid
+    version_map: HashMap<(u8, u16, u16, u16), u8>,
+
+    pcr_pid: Option<u16>,
+    last_pcr: u64,
+    /// Stream time in PCR ticks since first PCR
+    pcr_time: Option<u64>,
+
+    /// Difference between TDT/TOT time and reference time in seconds
+    clock_offset: Range,
+    /// Reference for the stream time without local clock:
+    /// first TDT/TOT time and stream time of it
+    clock_start: Option<(f64, f64)>,
+    has_local_clock: bool,
+}
+
+
+impl Analyzer {
+    /// Returns stream time in seconds defined by PCR
+    fn stream_time(&self) -> Option<f64> {
+        self.pcr_time.map(|v| v as f64 / PCR_RATE)
+    }
+
+    fn update_pcr(&mut self, packet: &[u8], pid: u16) {
+        if self.pcr_pid.is_some_and(|v| v != pid) {
+            return;
+        }
+
+        let pcr = match ts_pcr(packet) {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.pcr_pid = Some(pid);
+        self.pcr_time = Some(match self.pcr_time {
+            None => 0,
+            Some(time) => time + (pcr + PCR_WRAP - self.last_pcr) % PCR_WRAP,
+        });
+        self.last_pcr = pcr;
+    }
+
+    /// Processes TS packet.
+    /// `time` is a packet arrival time in seconds for live input, or None
+    /// to use PCR. `local_time` is a local clock in unix time to check TDT/TOT
+    pub fn push(&mut self, packet: &[u8], time: Option<f64>, local_time: Option<f64>) {
+        self.packets += 1;
+
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        self.update_pcr(packet, pid);
+
+        if pid != psi::EIT_PID && pid != psi::TDT_PID {
+            return;
+        }
+
+        let time = time.or_else(|| self.stream_time());
+
+        let stat = self.pid_map.entry(pid).or_default();
+        stat.packets += 1;
+        stat.check_cc(packet);
+
+        let mut section_list = Vec::new();
+        stat.reader.push(packet, &mut section_list);
+
+        for data in section_list {
+            self.push_section(&data, time, local_time);
+        }
+    }
+
+    fn push_section(&mut self, data: &[u8], time: Option<f64>, local_time: Option<f64>) {
+        let table_id = data[0];
+        let stat = self.table_map.entry(table_id).or_default();
+        stat.sections += 1;
+
+        if table_id == 0x70 || table_id == 0x73 {
+            if data.len() < 8 {
+                return;
+            }
+
+            let value = dump::mjd_time(&data[3 ..]) as f64;
+            let reference = match (local_time, time) {
+                (Some(local_time), _) => {
+                    self.has_local_clock = true;
+                    local_time
+                }
+                (None, Some(time)) => {
+                    let (start_value, start_time) = *self.clock_start.get_or_insert((value, time));
+                    start_value + time - start_time
+                }
+                _ => return,
+            };
+            self.clock_offset.push(value - reference);
+
+            if let Some(time) = time {
+                let prev = self.section_map.insert((table_id, 0, 0, 0, 0), time);
+                if let Some(prev) = prev {
+                    stat.interval.push(time - prev);
+                }
+            }
+            return;
+        }
+
+        // EIT sections only. sub-table identified by service_id, tsid and onid
+        if data.len() < 14 {
+            return;
+        }
+
+        let service_id = get_u16(&data[3 ..]);
+        let version = (data[5] >> 1) & 0x1F;
+        let section_number = data[6];
+        let tsid = get_u16(&data[8 ..]);
+        let onid = get_u16(&data[10 ..]);
+
+        let prev = self.version_map.insert((table_id, service_id, tsid, onid), version);
+        if prev.is_some_and(|v| v != version) {
+            stat.version_changes += 1;
+        }
+
+        if let Some(time) = time {
+            let key = (table_id, service_id, tsid, onid, section_number);
+            let prev = self.section_map.insert(key, time);
+            if let Some(prev) = prev {
+                stat.interval.push(time - prev);
+            }
+        }
+    }
+
+    /// Returns readable report
+    pub fn report(&self) -> String {
+        let mut dst = String::new();
+
+        let _ = writeln!(dst, "packets: {}", self.packets);
+        if self.pcr_pid.is_none() && ! self.has_local_clock {
+            let _ = writeln!(dst, "warning: PCR not found. Repetition intervals are not available");
+        }
+
+        for (pid, stat) in &self.pid_map {
+            let _ = writeln!(dst, "pid 0x{:04X}: packets {}, cc errors {}, crc errors {}",
+                pid, stat.packets, stat.cc_errors, stat.reader.crc_errors);
+        }
+
+        for (table_id, stat) in &self.table_map {
+            let _ = write!(dst, "table 0x{:02X}: sections {}, version changes {}",
+                table_id, stat.sections, stat.version_changes);
+            if stat.interval.count != 0 {
+                let _ = write!(dst, ", interval min {:.3} avg {:.3} max {:.3} s",
+                    stat.interval.min, stat.interval.avg(), stat.interval.max);
+            }
+            dst.push('\n');
+        }
+
+        if self.clock_offset.count != 0 {
+            let reference = if self.has_local_clock { "local clock" } else { "stream time" };
+            let _ = writeln!(dst, "tdt/tot offset to {}: min {:.3} avg {:.3} max {:.3} s",
+                reference, self.clock_offset.min, self.clock_offset.avg(), self.clock_offset.max);
+        }
+
+        dst
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::section,
+    };
+
+
+    /// Builds EIT present/following section without events
+    fn section(version: u8, section_number: u8) -> Vec<u8> {
+        let mut data = vec![
+            0x4E, 0xF0, 0x0F,
+            0x00, 0x01, 0xC1 | (version << 1), section_number, 0x01,
+            0x00, 0x01, 0x00, 0x01, 0x01, 0x4E,
+        ];
+        let crc = section::crc32(&data);
+        data.extend_from_slice(&crc.to_be_bytes());
+        data
+    }
+
+
+    /// Puts section into TS packet. Adaptation field with discontinuity_indicator if defined
+    fn packet(data: &[u8], cc: u8, is_discontinuity: bool) -> Vec<u8> {
+        let mut packet = vec![0x47, 0x40, psi::EIT_PID as u8];
+        if is_discontinuity {
+            packet.extend_from_slice(&[0x30 | cc, 0x01, 0x80]);
+        } else {
+            packet.push(0x10 | cc);
+        }
+        packet.push(0x00);
+        packet.extend_from_slice(data);
+        packet.resize(188, 0xFF);
+        packet
+    }
+
+
+    #[test]
+    fn test_analyzer() {
+        let mut analyzer = Analyzer::default();
+
+        analyzer.push(&packet(&section(1, 0), 0, false), Some(0.0), None);
+        analyzer.push(&packet(&section(1, 0), 1, false), Some(2.0), None);
+        // section repeated after 0.5 s with new version
+        analyzer.push(&packet(&section(2, 0), 2, false), Some(2.5), None);
+        // cc error
+        analyzer.push(&packet(&section(2, 1), 5, false), Some(3.0), None);
+        // counter changed with discontinuity_indicator
+        analyzer.push(&packet(&section(2, 1), 9, true), Some(4.0), None);
+
+        // crc error
+        let mut data = section(2, 0);
+        data[7] ^= 0xFF;
+        analyzer.push(&packet(&data, 10, false), Some(5.0), None);
+
+        let pid = &analyzer.pid_map[&psi::EIT_PID];
+        assert_eq!(pid.packets, 6);
+        assert_eq!(pid.cc_errors, 1);
+        assert_eq!(pid.reader.crc_errors, 1);
+
+        let table = &analyzer.table_map[&0x4E];
+        assert_eq!(table.sections, 5);
+        assert_eq!(table.version_changes, 1);
+        assert_eq!(table.interval.count, 3);
+        assert_eq!(table.interval.min, 0.5);
+        assert_eq!(table.interval.max, 2.0);
+    }
+}
@@ -0,0 +1,47 @@
+/// Schedule coverage of the one service in the time range
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// Events in the time range
+    pub events: usize,
+    /// Start time of the first event in the range
+    pub first: Option<u64>,
+    /// Stop time of the last event in the range
+    pub last: Option<u64>,
+    /// Time ranges without events between first and last event
+    pub gap_list: Vec<(u64, u64)>,
+    /// Time ranges covered by two events
+    pub overlap_list: Vec<(u64, u64)>,
+}
+
+
+impl Coverage {
+    /// Checks events in the range from `start` to `end`.
+    /// `event_list` is a list of start and stop times ordered by start time
+    pub fn new<I>(event_list: I, start: u64, end: u64) -> Self
+    where
+        I: IntoIterator<Item = (u64, u64)>,
+    {
+        let mut coverage = Coverage::default();
+
+        for (event_start, event_stop) in event_list {
+            if event_stop <= start || event_start >= end {
+                continue;
+            }
+
+            coverage.events += 1;
+
+            match coverage.last {
+                None => coverage.first = Some(event_start),
+                Some(last) if event_start > last => coverage.gap_list.push((last, event_start)),
+                Some(last) if event_start < last => {
+                    coverage.overlap_list.push((event_start, std::cmp::min(last, event_stop)));
+                }
+                _ => {},
+            }
+
+            coverage.last = Some(std::cmp::max(coverage.last.unwrap_or(0), event_stop));
+        }
+
+        coverage
+    }
+}
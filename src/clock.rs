@@ -0,0 +1,136 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+
+/// Paces output at constant rate against monotonic clock.
+/// Send time of the data defined by amount of data sent since clock start,
+/// so late wakeups and processing time do not reduce the rate
+#[derive(Debug)]
+pub struct Clock {
+    /// Rate in bytes per second
+    rate: u64,
+    start: Instant,
+    /// Bytes sent since start. Less than one second of data
+    sent: u64,
+}
+
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(0, Instant::now())
+    }
+}
+
+
+impl Clock {
+    pub fn new(rate: u64, now: Instant) -> Self {
+        Clock {
+            rate,
+            start: now,
+            sent: 0,
+        }
+    }
+
+    /// Changes rate. Clock restarts from `now`
+    pub fn set_rate(&mut self, rate: u64, now: Instant) {
+        if rate != self.rate {
+            *self = Clock::new(rate, now);
+        }
+    }
+
+    /// Returns time when `size` bytes could be sent
+    pub fn deadline(&self, size: u64) -> Instant {
+        if self.rate == 0 {
+            return self.start;
+        }
+
+        let nanos = ((self.sent + size) * NANOS_PER_SEC).div_ceil(self.rate);
+        self.start + Duration::from_nanos(nanos)
+    }
+
+    #[inline]
+    pub fn is_ready(&self, now: Instant, size: u64) -> bool {
+        self.deadline(size) <= now
+    }
+
+    /// Accounts sent data
+    pub fn consume(&mut self, size: u64) {
+        self.sent += size;
+
+        // keeps sent value small to prevent overflow
+        if self.rate != 0 && self.sent >= self.rate {
+            let secs = self.sent / self.rate;
+            self.start += Duration::from_secs(secs);
+            self.sent -= secs * self.rate;
+        }
+    }
+
+    /// Restarts clock if it lags behind `now` more than `max_lag`.
+    /// Prevents burst after idle period or long stall
+    pub fn sync(&mut self, now: Instant, max_lag: Duration) {
+        if now.saturating_duration_since(self.deadline(0)) > max_lag {
+            self.start = now;
+            self.sent = 0;
+        }
+    }
+}
+
+
+/// Measures average rate of the sent data
+#[derive(Debug)]
+pub struct RateMeter {
+    start: Instant,
+    bytes: u64,
+    /// Bytes since meter creation
+    total: u64,
+}
+
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        RateMeter {
+            start: Instant::now(),
+            bytes: 0,
+            total: 0,
+        }
+    }
+}
+
+
+impl RateMeter {
+    #[inline]
+    pub fn add(&mut self, size: usize) {
+        self.bytes += size as u64;
+        self.total += size as u64;
+    }
+
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns time elapsed since measurement start
+    #[inline]
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.start)
+    }
+
+    /// Returns average rate in kbit/s and restarts measurement
+    pub fn take(&mut self, now: Instant) -> f64 {
+        let elapsed = self.elapsed(now).as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            self.bytes as f64 * 8.0 / elapsed / 1000.0
+        } else {
+            0.0
+        };
+
+        self.start = now;
+        self.bytes = 0;
+        rate
+    }
+}
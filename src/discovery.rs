@@ -0,0 +1,225 @@
+use {
+    mpegts::psi,
+
+    crate::{
+        section::SectionReader,
+        text,
+    },
+};
+
+
+/// PAT PID
+const PAT_PID: u16 = 0x00;
+
+
+/// Service found in the PAT and SDT
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DiscoveredService {
+    pub pnr: u16,
+    /// Name from the service descriptor. Empty if service not found in SDT
+    pub name: String,
+    pub provider: String,
+    pub service_type: u8,
+}
+
+
+/// Transport stream parameters found in the PAT and SDT
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TransportStream {
+    pub tsid: u16,
+    /// Original network identifier. None if SDT not found
+    pub onid: Option<u16>,
+    pub service_list: Vec<DiscoveredService>,
+}
+
+
+#[inline]
+fn get_u16(data: &[u8]) -> u16 {
+    (u16::from(data[0]) << 8) | u16::from(data[1])
+}
+
+
+/// Converts DVB text into string without surrounding whitespace
+fn decode_text(data: &[u8]) -> String {
+    text::decode(data).trim().to_owned()
+}
+
+
+/// Sections of the one table version
+#[derive(Debug, Default)]
+struct Table {
+    version: u8,
+    section_list: Vec<Option<Vec<u8>>>,
+}
+
+
+impl Table {
+    /// Adds section into table.
+    /// Returns true if table is complete and has been changed
+    fn push(&mut self, data: Vec<u8>) -> bool {
+        let version = (data[5] >> 1) & 0x1F;
+        let number = usize::from(data[6]);
+        let last_number = usize::from(data[7]);
+        if number > last_number {
+            return false;
+        }
+
+        if version != self.version || self.section_list.len() != last_number + 1 {
+            self.version = version;
+            self.section_list = vec![None; last_number + 1];
+        }
+
+        if self.section_list[number].as_ref() == Some(&data) {
+            return false;
+        }
+
+        self.section_list[number] = Some(data);
+        self.is_complete()
+    }
+
+    #[inline]
+    fn is_complete(&self) -> bool {
+        ! self.section_list.is_empty() && self.section_list.iter().all(Option::is_some)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.section_list.iter().filter_map(|v| v.as_deref())
+    }
+}
+
+
+/// Learns transport stream parameters from the PAT and SDT actual
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pat_reader: SectionReader,
+    sdt_reader: SectionReader,
+    pat: Table,
+    sdt: Table,
+    ts: Option<TransportStream>,
+    is_updated: bool,
+}
+
+
+impl Discovery {
+    /// Processes TS packet. Packets with PID other than PAT or SDT are ignored
+    pub fn push(&mut self, packet: &[u8], pid: u16) {
+        let mut section_list = Vec::new();
+        let mut is_changed = false;
+
+        match pid {
+            PAT_PID => {
+                self.pat_reader.push(packet, &mut section_list);
+                for data in section_list {
+                    if data[0] == 0x00 {
+                        is_changed |= self.pat.push(data);
+                    }
+                }
+            }
+            psi::SDT_PID => {
+                self.sdt_reader.push(packet, &mut section_list);
+                for data in section_list {
+                    if data[0] == 0x42 {
+                        is_changed |= self.sdt.push(data);
+                    }
+                }
+            }
+            _ => return,
+        }
+
+        if is_changed {
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        if ! self.pat.is_complete() {
+            return;
+        }
+
+        let mut ts = TransportStream::default();
+
+        for data in self.pat.iter() {
+            ts.tsid = get_u16(&data[3 ..]);
+            let end = data.len() - 4;
+            for item in data[8 .. end].chunks_exact(4) {
+                let pnr = get_u16(item);
+                // skip network PID
+                if pnr != 0 {
+                    ts.service_list.push(DiscoveredService {
+                        pnr,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        if self.sdt.is_complete() {
+            for data in self.sdt.iter() {
+                ts.onid = Some(get_u16(&data[8 ..]));
+                parse_sdt(data, &mut ts.service_list);
+            }
+        }
+
+        if self.ts.as_ref() != Some(&ts) {
+            self.ts = Some(ts);
+            self.is_updated = true;
+        }
+    }
+
+    /// Returns transport stream parameters if changed since last call
+    pub fn take_update(&mut self) -> Option<&TransportStream> {
+        if ! self.is_updated {
+            return None;
+        }
+
+        self.is_updated = false;
+        self.ts.as_ref()
+    }
+
+    /// Reports last known parameters on next `take_update()` call
+    pub fn refresh(&mut self) {
+        self.is_updated = self.ts.is_some();
+    }
+}
+
+
+/// Fills service names from the SDT section.
+/// Services not found in the PAT are ignored
+fn parse_sdt(data: &[u8], service_list: &mut [DiscoveredService]) {
+    let end = data.len() - 4;
+    let mut skip = 11;
+
+    while skip + 5 <= end {
+        let pnr = get_u16(&data[skip ..]);
+        let loop_len = (usize::from(data[skip + 3] & 0x0F) << 8) | usize::from(data[skip + 4]);
+        skip += 5;
+
+        let loop_end = std::cmp::min(skip + loop_len, end);
+        let service = service_list.iter_mut().find(|s| s.pnr == pnr);
+
+        if let Some(service) = service {
+            while skip + 2 <= loop_end {
+                let tag = data[skip];
+                let len = usize::from(data[skip + 1]);
+                let desc = &data[skip + 2 .. std::cmp::min(skip + 2 + len, loop_end)];
+                skip += 2 + len;
+
+                // service descriptor
+                if tag != 0x48 || desc.len() < 3 {
+                    continue;
+                }
+
+                let provider_len = usize::from(desc[1]);
+                let provider = desc.get(2 .. 2 + provider_len).unwrap_or(&[]);
+                let name = desc.get(3 + provider_len ..).unwrap_or(&[]);
+                let name_len = usize::from(desc.get(2 + provider_len).cloned().unwrap_or(0));
+
+                service.service_type = desc[0];
+                service.provider = decode_text(provider);
+                service.name = decode_text(&name[.. std::cmp::min(name_len, name.len())]);
+            }
+        }
+
+        skip = loop_end;
+    }
+}
@@ -0,0 +1,489 @@
+use {
+    std::fmt::Write,
+
+    chrono::TimeZone,
+
+    crate::{
+        json,
+        text,
+    },
+};
+
+
+/// Unix time of the MJD epoch
+const MJD_UNIX_EPOCH: u64 = 40587;
+
+
+#[inline]
+fn get_u16(data: &[u8]) -> u16 {
+    (u16::from(data[0]) << 8) | u16::from(data[1])
+}
+
+
+#[inline]
+fn bcd(value: u8) -> u64 {
+    u64::from(value >> 4) * 10 + u64::from(value & 0x0F)
+}
+
+
+/// Converts BCD encoded hours, minutes, seconds into seconds
+fn bcd_time(data: &[u8]) -> u64 {
+    bcd(data[0]) * 3600 + bcd(data[1]) * 60 + bcd(data[2])
+}
+
+
+/// Converts MJD date and BCD time into unix time
+pub fn mjd_time(data: &[u8]) -> u64 {
+    let mjd = u64::from(get_u16(data));
+    mjd.saturating_sub(MJD_UNIX_EPOCH) * 86400 + bcd_time(&data[2 ..])
+}
+
+
+fn lang(data: &[u8]) -> String {
+    String::from_utf8_lossy(&data[.. 3]).into_owned()
+}
+
+
+/// Decoded descriptor of the EIT event
+#[derive(Debug)]
+pub enum Descriptor {
+    /// Short event descriptor: language, name, text
+    ShortEvent(String, String, String),
+    /// Extended event descriptor: number, last number, language, items, text
+    ExtendedEvent(u8, u8, String, Vec<(String, String)>, String),
+    /// Content descriptor: list of content nibbles
+    Content(Vec<u8>),
+    /// Parental rating descriptor: list of country code and rating
+    ParentalRating(Vec<(String, u8)>),
+    /// Not supported descriptor: tag and payload
+    Other(u8, Vec<u8>),
+}
+
+
+impl Descriptor {
+    fn parse(tag: u8, data: &[u8]) -> Self {
+        Self::parse_known(tag, data)
+            .unwrap_or_else(|| Descriptor::Other(tag, data.to_vec()))
+    }
+
+    fn parse_known(tag: u8, data: &[u8]) -> Option<Self> {
+        match tag {
+            0x4D => {
+                let name_len = usize::from(*data.get(3)?);
+                let name = data.get(4 .. 4 + name_len)?;
+                let text_len = usize::from(*data.get(4 + name_len)?);
+                let text = data.get(5 + name_len .. 5 + name_len + text_len)?;
+                Some(Descriptor::ShortEvent(lang(data), text::decode(name), text::decode(text)))
+            }
+            0x4E => {
+                let number = data.first()? >> 4;
+                let last_number = data[0] & 0x0F;
+                let items_len = usize::from(*data.get(4)?);
+                let items = data.get(5 .. 5 + items_len)?;
+
+                let mut item_list = Vec::new();
+                let mut skip = 0;
+                while skip < items.len() {
+                    let desc_len = usize::from(items[skip]);
+                    let desc = items.get(skip + 1 .. skip + 1 + desc_len)?;
+                    skip += 1 + desc_len;
+                    let item_len = usize::from(*items.get(skip)?);
+                    let item = items.get(skip + 1 .. skip + 1 + item_len)?;
+                    skip += 1 + item_len;
+                    item_list.push((text::decode(desc), text::decode(item)));
+                }
+
+                let text_len = usize::from(*data.get(5 + items_len)?);
+                let text = data.get(6 + items_len .. 6 + items_len + text_len)?;
+                Some(Descriptor::ExtendedEvent(number, last_number, lang(&data[1 ..]), item_list, text::decode(text)))
+            }
+            0x54 => {
+                Some(Descriptor::Content(data.chunks_exact(2).map(|v| v[0]).collect()))
+            }
+            0x55 => {
+                let item_list = data.chunks_exact(4)
+                    .map(|v| (lang(v), v[3]))
+                    .collect();
+                Some(Descriptor::ParentalRating(item_list))
+            }
+            _ => None,
+        }
+    }
+}
+
+
+/// Decoded event of the EIT section
+#[derive(Debug, Default)]
+pub struct Event {
+    pub event_id: u16,
+    /// Start time in unix time
+    pub start: u64,
+    /// Duration in seconds
+    pub duration: u64,
+    pub running_status: u8,
+    pub free_ca_mode: u8,
+    pub descriptor_list: Vec<Descriptor>,
+}
+
+
+/// Decoded EIT section
+#[derive(Debug, Default)]
+pub struct Section {
+    pub table_id: u8,
+    pub service_id: u16,
+    pub version: u8,
+    pub section_number: u8,
+    pub last_section_number: u8,
+    pub tsid: u16,
+    pub onid: u16,
+    pub segment_last_section_number: u8,
+    pub last_table_id: u8,
+    /// Full section size including header and CRC
+    pub size: usize,
+    pub event_list: Vec<Event>,
+}
+
+
+impl Section {
+    /// Parses EIT section. Returns None if section is invalid
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 18 {
+            return None;
+        }
+
+        let mut section = Section {
+            table_id: data[0],
+            service_id: get_u16(&data[3 ..]),
+            version: (data[5] >> 1) & 0x1F,
+            section_number: data[6],
+            last_section_number: data[7],
+            tsid: get_u16(&data[8 ..]),
+            onid: get_u16(&data[10 ..]),
+            segment_last_section_number: data[12],
+            last_table_id: data[13],
+            size: data.len(),
+            event_list: Vec::new(),
+        };
+
+        let end = data.len() - 4;
+        let mut skip = 14;
+
+        while skip + 12 <= end {
+            let item = &data[skip ..];
+            let desc_len = usize::from(get_u16(&item[10 ..]) & 0x0FFF);
+
+            let mut event = Event {
+                event_id: get_u16(item),
+                start: mjd_time(&item[2 ..]),
+                duration: bcd_time(&item[7 ..]),
+                running_status: item[10] >> 5,
+                free_ca_mode: (item[10] >> 4) & 0x01,
+                descriptor_list: Vec::new(),
+            };
+
+            skip += 12;
+            let desc_end = std::cmp::min(skip + desc_len, end);
+
+            while skip + 2 <= desc_end {
+                let tag = data[skip];
+                let len = usize::from(data[skip + 1]);
+                let desc = &data[skip + 2 .. std::cmp::min(skip + 2 + len, desc_end)];
+                event.descriptor_list.push(Descriptor::parse(tag, desc));
+                skip += 2 + len;
+            }
+
+            skip = desc_end;
+            section.event_list.push(event);
+        }
+
+        Some(section)
+    }
+}
+
+
+/// Formats unix time in UTC
+fn format_time(timestamp: u64) -> String {
+    chrono::Utc.timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{:02X}", v)).collect()
+}
+
+
+/// Appends readable listing of the sections
+pub fn write_text(dst: &mut String, output: &str, section_list: &[Section]) {
+    let _ = writeln!(dst, "output {}", output);
+
+    for s in section_list {
+        let _ = writeln!(dst,
+            "  table 0x{:02X} service {} tsid {} onid {} version {} section {}/{} segment last {} last table 0x{:02X} size {}",
+            s.table_id, s.service_id, s.tsid, s.onid, s.version,
+            s.section_number, s.last_section_number,
+            s.segment_last_section_number, s.last_table_id, s.size);
+
+        for e in &s.event_list {
+            let _ = writeln!(dst,
+                "    event {} start {} UTC duration {:02}:{:02}:{:02} status {} ca {}",
+                e.event_id, format_time(e.start),
+                e.duration / 3600, e.duration % 3600 / 60, e.duration % 60,
+                e.running_status, e.free_ca_mode);
+
+            for d in &e.descriptor_list {
+                let _ = match d {
+                    Descriptor::ShortEvent(lang, name, text) => writeln!(dst,
+                        "      short event [{}] {:?} {:?}", lang, name, text),
+                    Descriptor::ExtendedEvent(number, last_number, lang, item_list, text) => {
+                        let _ = writeln!(dst,
+                            "      extended event {}/{} [{}] {:?}", number, last_number, lang, text);
+                        for (desc, item) in item_list {
+                            let _ = writeln!(dst, "        {:?}: {:?}", desc, item);
+                        }
+                        Ok(())
+                    }
+                    Descriptor::Content(list) => {
+                        let list: Vec<String> = list.iter().map(|v| format!("0x{:02X}", v)).collect();
+                        writeln!(dst, "      content {}", list.join(" "))
+                    }
+                    Descriptor::ParentalRating(list) => {
+                        let list: Vec<String> = list.iter().map(|(c, r)| format!("{}:{}", c, r)).collect();
+                        writeln!(dst, "      parental rating {}", list.join(" "))
+                    }
+                    Descriptor::Other(tag, data) => writeln!(dst,
+                        "      descriptor 0x{:02X} {}", tag, hex(data)),
+                };
+            }
+        }
+    }
+}
+
+
+fn push_text_field(dst: &mut String, key: &str, value: &str) {
+    dst.push(',');
+    json::push_key(dst, key);
+    json::push_str(dst, value);
+}
+
+
+fn write_json_descriptor(dst: &mut String, d: &Descriptor) {
+    match d {
+        Descriptor::ShortEvent(lang, name, text) => {
+            dst.push_str("{\"tag\":77");
+            push_text_field(dst, "lang", lang);
+            push_text_field(dst, "name", name);
+            push_text_field(dst, "text", text);
+        }
+        Descriptor::ExtendedEvent(number, last_number, lang, item_list, text) => {
+            let _ = write!(dst, "{{\"tag\":78,\"number\":{},\"last_number\":{}", number, last_number);
+            push_text_field(dst, "lang", lang);
+            dst.push_str(",\"items\":[");
+            for (i, (desc, item)) in item_list.iter().enumerate() {
+                if i != 0 {
+                    dst.push(',');
+                }
+                dst.push('[');
+                json::push_str(dst, desc);
+                dst.push(',');
+                json::push_str(dst, item);
+                dst.push(']');
+            }
+            dst.push(']');
+            push_text_field(dst, "text", text);
+        }
+        Descriptor::Content(list) => {
+            let list: Vec<String> = list.iter().map(u8::to_string).collect();
+            let _ = write!(dst, "{{\"tag\":84,\"content\":[{}]", list.join(","));
+        }
+        Descriptor::ParentalRating(list) => {
+            dst.push_str("{\"tag\":85,\"rating\":[");
+            for (i, (country, rating)) in list.iter().enumerate() {
+                if i != 0 {
+                    dst.push(',');
+                }
+                dst.push('{');
+                json::push_key(dst, "country");
+                json::push_str(dst, country);
+                let _ = write!(dst, ",\"rating\":{}}}", rating);
+            }
+            dst.push(']');
+        }
+        Descriptor::Other(tag, data) => {
+            let _ = write!(dst, "{{\"tag\":{}", tag);
+            push_text_field(dst, "data", &hex(data));
+        }
+    }
+    dst.push('}');
+}
+
+
+/// Appends sections of the one output as JSON object
+pub fn write_json(dst: &mut String, output: &str, section_list: &[Section]) {
+    dst.push('{');
+    json::push_key(dst, "output");
+    json::push_str(dst, output);
+    dst.push_str(",\"sections\":[");
+
+    for (i, s) in section_list.iter().enumerate() {
+        if i != 0 {
+            dst.push(',');
+        }
+        let _ = write!(dst,
+            "{{\"table_id\":{},\"service_id\":{},\"tsid\":{},\"onid\":{},\"version\":{},\
+            \"section_number\":{},\"last_section_number\":{},\
+            \"segment_last_section_number\":{},\"last_table_id\":{},\"size\":{},\"events\":[",
+            s.table_id, s.service_id, s.tsid, s.onid, s.version,
+            s.section_number, s.last_section_number,
+            s.segment_last_section_number, s.last_table_id, s.size);
+
+        for (j, e) in s.event_list.iter().enumerate() {
+            if j != 0 {
+                dst.push(',');
+            }
+            let _ = write!(dst,
+                "{{\"event_id\":{},\"start\":{},\"duration\":{},\"running_status\":{},\"free_ca_mode\":{},\"descriptors\":[",
+                e.event_id, e.start, e.duration, e.running_status, e.free_ca_mode);
+            for (k, d) in e.descriptor_list.iter().enumerate() {
+                if k != 0 {
+                    dst.push(',');
+                }
+                write_json_descriptor(dst, d);
+            }
+            dst.push_str("]}");
+        }
+
+        dst.push_str("]}");
+    }
+
+    dst.push_str("]}");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::section,
+    };
+
+
+    /// Builds EIT section with one event
+    fn section(descriptors: &[u8]) -> Vec<u8> {
+        let mut data = vec![
+            0x50, 0xF0, 0x00,
+            0x00, 0x65, 0xC5, 0x08, 0x10,
+            0x00, 0x02, 0x00, 0x01, 0x08, 0x51,
+            // event 1234: 2026-10-16 12:30:00, 01:45:00, running
+            0x04, 0xD2, 0xEF, 0x91, 0x12, 0x30, 0x00, 0x01, 0x45, 0x00,
+            0x80 | (descriptors.len() >> 8) as u8, descriptors.len() as u8,
+        ];
+        data.extend_from_slice(descriptors);
+
+        let size = data.len() + 4 - 3;
+        data[1] |= (size >> 8) as u8;
+        data[2] = size as u8;
+
+        let crc = section::crc32(&data);
+        data.extend_from_slice(&crc.to_be_bytes());
+        data
+    }
+
+
+    #[test]
+    fn test_parse_header() {
+        let data = section(&[]);
+        let s = Section::parse(&data).unwrap();
+
+        assert_eq!(s.table_id, 0x50);
+        assert_eq!(s.service_id, 101);
+        assert_eq!(s.version, 2);
+        assert_eq!(s.section_number, 8);
+        assert_eq!(s.last_section_number, 16);
+        assert_eq!(s.tsid, 2);
+        assert_eq!(s.onid, 1);
+        assert_eq!(s.segment_last_section_number, 8);
+        assert_eq!(s.last_table_id, 0x51);
+        assert_eq!(s.size, data.len());
+
+        assert_eq!(s.event_list.len(), 1);
+        let e = &s.event_list[0];
+        assert_eq!(e.event_id, 1234);
+        assert_eq!(e.start, 1_792_153_800);
+        assert_eq!(e.duration, 6300);
+        assert_eq!(e.running_status, 4);
+        assert_eq!(e.free_ca_mode, 0);
+        assert!(e.descriptor_list.is_empty());
+    }
+
+
+    #[test]
+    fn test_parse_descriptors() {
+        let data = section(&[
+            // short event
+            0x4D, 0x0E, b'e', b'n', b'g',
+            0x04, b'N', b'e', b'w', b's',
+            0x05, b'D', b'a', b'i', b'l', b'y',
+            // extended event
+            0x4E, 0x14, 0x01, b'e', b'n', b'g',
+            0x0A, 0x03, b'D', b'i', b'r', 0x05, b'S', b'm', b'i', b't', b'h',
+            0x04, b'L', b'o', b'n', b'g',
+            // content
+            0x54, 0x04, 0x20, 0x00, 0x43, 0x00,
+            // parental rating
+            0x55, 0x04, b'G', b'B', b'R', 0x09,
+            // private data specifier
+            0x5F, 0x04, 0x00, 0x00, 0x00, 0x28,
+        ]);
+        let s = Section::parse(&data).unwrap();
+        let list = &s.event_list[0].descriptor_list;
+        assert_eq!(list.len(), 5);
+
+        match &list[0] {
+            Descriptor::ShortEvent(lang, name, text) => {
+                assert_eq!((lang.as_str(), name.as_str(), text.as_str()), ("eng", "News", "Daily"));
+            }
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+
+        match &list[1] {
+            Descriptor::ExtendedEvent(number, last_number, lang, item_list, text) => {
+                assert_eq!((*number, *last_number, lang.as_str()), (0, 1, "eng"));
+                assert_eq!(item_list, &[("Dir".to_owned(), "Smith".to_owned())]);
+                assert_eq!(text, "Long");
+            }
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+
+        match &list[2] {
+            Descriptor::Content(v) => assert_eq!(v, &[0x20, 0x43]),
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+
+        match &list[3] {
+            Descriptor::ParentalRating(v) => assert_eq!(v, &[("GBR".to_owned(), 9)]),
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+
+        match &list[4] {
+            Descriptor::Other(tag, v) => assert_eq!((*tag, v.as_slice()), (0x5F, &[0, 0, 0, 0x28][..])),
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+    }
+
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Section::parse(&[0x4E, 0xF0, 0x0F]).is_none());
+
+        // name length out of descriptor
+        let data = section(&[ 0x4D, 0x05, b'e', b'n', b'g', 0x10, b'N' ]);
+        let s = Section::parse(&data).unwrap();
+        match &s.event_list[0].descriptor_list[0] {
+            Descriptor::Other(tag, _) => assert_eq!(*tag, 0x4D),
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+    }
+}
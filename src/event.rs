@@ -0,0 +1,434 @@
+use {
+    std::{
+        cmp,
+        collections::HashMap,
+    },
+
+    epg::EpgEvent,
+
+    mpegts::{
+        psi::{
+            EitItem,
+            Descriptors,
+            Desc4D,
+            Desc4E,
+            Desc54,
+            Desc54i,
+            Desc55,
+            Desc55i,
+        },
+        textcode::{
+            self,
+            StringDVB,
+        },
+    },
+
+    crate::{
+        genre::GenreMap,
+        section,
+    },
+};
+
+
+/// Maximum size of the descriptor payload
+const DESC_MAX_SIZE: usize = 255;
+/// Short event descriptor: language code, name length, text length
+const DESC_4D_HEADER: usize = 3 + 1 + 1;
+/// Extended event descriptor: numbers, language code, items length, text length
+const DESC_4E_HEADER: usize = 1 + 3 + 1 + 1;
+/// Maximum number of the extended event descriptors for one event.
+/// Limited with 4-bit descriptor number
+const DESC_4E_LIMIT: usize = 16;
+/// Maximum number of the content items. 2 bytes per item
+const DESC_54_LIMIT: usize = DESC_MAX_SIZE / 2;
+/// Maximum number of the parental rating items. 4 bytes per item
+const DESC_55_LIMIT: usize = DESC_MAX_SIZE / 4;
+/// Maximum size of the event descriptors to fit into one EIT section:
+/// section size without section header, CRC, and event header
+const EVENT_DESC_MAX_SIZE: usize = 4096 - 14 - 4 - 12;
+
+
+/// Options to convert XMLTV events into EIT items
+#[derive(Debug, Default)]
+pub struct EventOptions {
+    /// Keep trimmed description in the short event descriptor
+    /// if description moved to the extended event descriptors
+    pub short_text: bool,
+    /// Mapping of the XMLTV categories to the DVB content
+    pub genre_map: GenreMap,
+}
+
+
+/// Returns size of the character table prefix
+fn charset_size(codepage: u8) -> usize {
+    match codepage {
+        1 ..= 4 | 13 ..= 15 => 3,
+        5 ..= 11 | 21 => 1,
+        _ => 0,
+    }
+}
+
+
+/// Returns size of the encoded character
+fn char_size(c: char, codepage: u8) -> usize {
+    match codepage {
+        21 => c.len_utf8(),
+        // ISO 6937 encodes diacritical marks as separate byte
+        0 if ! c.is_ascii() => 2,
+        _ => 1,
+    }
+}
+
+
+/// Returns size of the encoded text including character table prefix
+fn text_size(text: &str, codepage: u8) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+
+    charset_size(codepage) + text.chars().map(|c| char_size(c, codepage)).sum::<usize>()
+}
+
+
+/// Returns longest prefix of the text which fits into `limit` bytes.
+/// If `word` is true prefix is cut on the word boundary when possible
+fn trim_text(text: &str, codepage: u8, limit: usize, word: bool) -> &str {
+    let mut size = charset_size(codepage);
+    if size >= limit {
+        return "";
+    }
+
+    let mut last_space = None;
+
+    for (pos, c) in text.char_indices() {
+        size += char_size(c, codepage);
+        if size > limit {
+            return match last_space {
+                Some(v) if word => text[.. v].trim_end(),
+                _ => &text[.. pos],
+            };
+        }
+
+        if c.is_whitespace() {
+            last_space = Some(pos);
+        }
+    }
+
+    text
+}
+
+
+/// Splits text into chunks for the extended event descriptors.
+/// `budget` is a total size of the descriptors including tag and length
+fn split_text(text: &str, codepage: u8, mut budget: usize) -> Vec<&str> {
+    let mut chunk_list = Vec::new();
+    let mut text = text.trim();
+
+    while ! text.is_empty() && chunk_list.len() < DESC_4E_LIMIT {
+        let limit = cmp::min(
+            DESC_MAX_SIZE - DESC_4E_HEADER,
+            budget.saturating_sub(2 + DESC_4E_HEADER));
+
+        let mut chunk = trim_text(text, codepage, limit, true);
+        if chunk.is_empty() {
+            chunk = trim_text(text, codepage, limit, false);
+            if chunk.is_empty() {
+                break;
+            }
+        }
+
+        chunk_list.push(chunk);
+        budget -= 2 + DESC_4E_HEADER + text_size(chunk, codepage);
+        text = text[chunk.len() ..].trim_start();
+    }
+
+    chunk_list
+}
+
+
+fn lang_code(event: &EpgEvent) -> StringDVB {
+    let lang = if event.lang.len() == 3 {
+        event.lang.as_str()
+    } else {
+        "und"
+    };
+
+    StringDVB::from_str(lang, textcode::ISO6937)
+}
+
+
+/// Builds short event descriptor and extended event descriptors if
+/// description doesn't fit into the short event descriptor.
+/// `budget` is a size available for the descriptors in the section
+fn push_text(descriptors: &mut Descriptors,
+    event: &EpgEvent,
+    codepage: u8,
+    options: &EventOptions,
+    budget: usize)
+{
+    let name_limit = DESC_MAX_SIZE - DESC_4D_HEADER;
+    let name = trim_text(&event.title, codepage, name_limit, false);
+
+    let text_limit = name_limit - text_size(name, codepage);
+    let desc = event.desc.trim();
+
+    if text_size(desc, codepage) <= text_limit {
+        descriptors.push(Desc4D {
+            lang: lang_code(event),
+            name: StringDVB::from_str(name, codepage),
+            text: StringDVB::from_str(desc, codepage),
+        });
+        return;
+    }
+
+    let text = if options.short_text {
+        trim_text(desc, codepage, text_limit, true)
+    } else {
+        ""
+    };
+
+    descriptors.push(Desc4D {
+        lang: lang_code(event),
+        name: StringDVB::from_str(name, codepage),
+        text: StringDVB::from_str(text, codepage),
+    });
+
+    let short_size = 2 + DESC_4D_HEADER + text_size(name, codepage) + text_size(text, codepage);
+    let chunk_list = split_text(desc, codepage, budget.saturating_sub(short_size));
+    if chunk_list.is_empty() {
+        return;
+    }
+    let last_number = (chunk_list.len() - 1) as u8;
+
+    for (number, chunk) in chunk_list.into_iter().enumerate() {
+        descriptors.push(Desc4E {
+            number: number as u8,
+            last_number,
+            lang: lang_code(event),
+            items: Vec::new(),
+            text: StringDVB::from_str(chunk, codepage),
+        });
+    }
+}
+
+
+fn content_desc(event: &EpgEvent, genre_map: &GenreMap) -> Option<Desc54> {
+    let mut content_list: Vec<u8> = Vec::new();
+    for category in &event.category {
+        if let Some(content) = genre_map.get(category) {
+            if ! content_list.contains(&content) {
+                content_list.push(content);
+            }
+        }
+    }
+
+    if content_list.is_empty() {
+        return None;
+    }
+
+    content_list.truncate(DESC_54_LIMIT);
+
+    let mut desc = Desc54::default();
+    for content in content_list {
+        desc.items.push(Desc54i {
+            content_nibble_level_1: content >> 4,
+            content_nibble_level_2: content & 0x0F,
+            user_byte: 0,
+        });
+    }
+
+    Some(desc)
+}
+
+
+fn parental_rating_desc(parental_rating: &HashMap<[u8; 3], u8>) -> Option<Desc55> {
+    if parental_rating.is_empty() {
+        return None;
+    }
+
+    // sorted to keep same table on each build
+    let mut rating_list: Vec<(&[u8; 3], &u8)> = parental_rating.iter().collect();
+    rating_list.sort();
+    rating_list.truncate(DESC_55_LIMIT);
+
+    let mut desc = Desc55::default();
+    for (country, &age) in rating_list {
+        let country = String::from_utf8_lossy(country);
+        desc.items.push(Desc55i {
+            country_code: StringDVB::from_str(&country, textcode::ISO6937),
+            // DVB rating is minimum age minus 3 years
+            rating: age.saturating_sub(3),
+        });
+    }
+
+    Some(desc)
+}
+
+
+/// Returns event identifier for the event start time.
+/// Identifier is the number of minutes since Unix epoch modulo 65536.
+/// So it's stable on XMLTV reload and restart, and unique per service
+/// in the 45 days range which is more than the EIT schedule period
+pub fn event_id(start: u64) -> u16 {
+    (start / 60) as u16
+}
+
+
+/// Identifiers for events starting in the same minute are taken from minutes
+/// about 23 days away from the event start (half of the identifier range).
+/// Schedule is limited to 16 days, so identifiers of other events are not taken
+const EVENT_ID_SHIFT: u16 = 0x8000;
+/// Range of identifiers for events starting in the same minute. About 6 days
+const EVENT_ID_SPREAD: u16 = 0x2000;
+
+
+/// Returns event identifier unique per service.
+/// Identifier is defined with `event_id()` or, if it's already used,
+/// with hash of the start time and title.
+/// `insert` returns false if identifier is already used
+pub fn unique_event_id<F>(start: u64, title: &str, mut insert: F) -> u16
+where
+    F: FnMut(u16) -> bool,
+{
+    let event_id = event_id(start);
+    if insert(event_id) {
+        return event_id;
+    }
+
+    let mut data = start.to_be_bytes().to_vec();
+    data.extend_from_slice(title.as_bytes());
+    let hash = (section::crc32(&data) % u32::from(EVENT_ID_SPREAD)) as u16;
+
+    let mut event_id = event_id
+        .wrapping_add(EVENT_ID_SHIFT - EVENT_ID_SPREAD / 2)
+        .wrapping_add(hash);
+    while ! insert(event_id) {
+        event_id = event_id.wrapping_add(1);
+    }
+
+    event_id
+}
+
+
+/// Builds synthetic event for the gap between events
+pub fn filler_item(start: u64, stop: u64, title: &str, codepage: u8) -> EitItem {
+    let mut item = EitItem {
+        event_id: event_id(start),
+        start,
+        duration: stop.saturating_sub(start) as u32,
+        ..Default::default()
+    };
+
+    let title = trim_text(title, codepage, DESC_MAX_SIZE - DESC_4D_HEADER, false);
+    item.descriptors.push(Desc4D {
+        lang: StringDVB::from_str("und", textcode::ISO6937),
+        name: StringDVB::from_str(title, codepage),
+        text: StringDVB::from_str("", codepage),
+    });
+
+    item
+}
+
+
+/// Converts XMLTV event into EIT item.
+/// `parental_rating` is a minimum age by country code
+pub fn eit_item(event: &EpgEvent,
+    codepage: u8,
+    parental_rating: &HashMap<[u8; 3], u8>,
+    options: &EventOptions) -> EitItem
+{
+    let mut item = EitItem::from(event);
+    item.descriptors = Descriptors::default();
+
+    let content = content_desc(event, &options.genre_map);
+    let parental_rating = parental_rating_desc(parental_rating);
+
+    // text descriptors take space left by other descriptors
+    let mut budget = EVENT_DESC_MAX_SIZE;
+    if let Some(desc) = &content {
+        budget -= 2 + desc.items.len() * 2;
+    }
+    if let Some(desc) = &parental_rating {
+        budget -= 2 + desc.items.len() * 4;
+    }
+
+    push_text(&mut item.descriptors, event, codepage, options, budget);
+    if let Some(desc) = content {
+        item.descriptors.push(desc);
+    }
+    if let Some(desc) = parental_rating {
+        item.descriptors.push(desc);
+    }
+
+    item
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::collections::HashSet,
+    };
+
+
+    const START: u64 = 1_600_000_000 - 1_600_000_000 % 60;
+
+
+    /// Returns identifiers for the list of events: start time and title
+    fn id_list(event_list: &[(u64, &str)]) -> Vec<u16> {
+        let mut event_id_list = HashSet::new();
+        event_list.iter()
+            .map(|&(start, title)| unique_event_id(start, title, |v| event_id_list.insert(v)))
+            .collect()
+    }
+
+
+    #[test]
+    fn test_unique_event_id() {
+        // every minute for 16 days with short events in the same minute
+        let mut event_list = Vec::new();
+        for i in 0 .. 16 * 1440 {
+            event_list.push((START + i * 60, "Event"));
+            if i % 10 == 0 {
+                event_list.push((START + i * 60 + 20, "News"));
+                event_list.push((START + i * 60 + 40, "Weather"));
+            }
+        }
+
+        let id_list = id_list(&event_list);
+        let set: HashSet<u16> = id_list.iter().cloned().collect();
+        assert_eq!(set.len(), event_list.len());
+
+        // events starting at the beginning of the minute keep minute identifier
+        for (&(start, title), &id) in event_list.iter().zip(&id_list) {
+            if title == "Event" {
+                assert_eq!(id, event_id(start));
+            }
+        }
+    }
+
+
+    #[test]
+    fn test_stable_event_id() {
+        let event_list = [
+            (START, "Event"),
+            (START + 30, "News"),
+            (START + 60, "Weather"),
+            (START + 90, "Sport"),
+        ];
+        let first = id_list(&event_list);
+
+        // finished events removed on reload
+        let second = id_list(&event_list[1 ..]);
+        assert_eq!(&first[2 ..], &second[1 ..]);
+
+        // neighbours keep identifiers
+        assert_eq!(first[0], event_id(START));
+        assert_eq!(first[2], event_id(START + 60));
+        assert_ne!(first[1], event_id(START) + 1);
+        assert_ne!(first[3], event_id(START + 60) + 1);
+
+        assert_eq!(first, id_list(&event_list));
+    }
+}
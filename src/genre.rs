@@ -0,0 +1,199 @@
+use std::{
+    io::{
+        self,
+        BufRead,
+        BufReader,
+    },
+    fs::File,
+    collections::HashMap,
+};
+
+
+#[derive(Debug, Error)]
+#[error_prefix = "Genre"]
+pub enum GenreError {
+    #[error_from]
+    Io(io::Error),
+    #[error_kind("invalid format at line {}", 0)]
+    Format(usize),
+}
+
+
+type Result<T> = std::result::Result<T, GenreError>;
+
+
+/// Default mapping of the XMLTV categories to the DVB content
+/// (content_nibble_level_1 and content_nibble_level_2). EN 300 468 Table 29
+const DEFAULT_MAP: &[(&str, u8)] = &[
+    // Movie/Drama
+    ("movie", 0x10),
+    ("film", 0x10),
+    ("drama", 0x10),
+    ("detective", 0x11),
+    ("thriller", 0x11),
+    ("crime", 0x11),
+    ("adventure", 0x12),
+    ("western", 0x12),
+    ("war", 0x12),
+    ("science fiction", 0x13),
+    ("sci-fi", 0x13),
+    ("fantasy", 0x13),
+    ("horror", 0x13),
+    ("comedy", 0x14),
+    ("sitcom", 0x14),
+    ("soap", 0x15),
+    ("melodrama", 0x15),
+    ("romance", 0x16),
+    ("historical", 0x17),
+    ("adult", 0x18),
+    // News/Current affairs
+    ("news", 0x20),
+    ("weather", 0x21),
+    ("news magazine", 0x22),
+    ("documentary", 0x23),
+    ("interview", 0x24),
+    ("debate", 0x24),
+    // Show/Game show
+    ("show", 0x30),
+    ("game show", 0x31),
+    ("quiz", 0x31),
+    ("variety", 0x32),
+    ("talk show", 0x33),
+    // Sports
+    ("sports", 0x40),
+    ("sport", 0x40),
+    ("sports magazine", 0x42),
+    ("football", 0x43),
+    ("soccer", 0x43),
+    ("tennis", 0x44),
+    ("team sports", 0x45),
+    ("athletics", 0x46),
+    ("motor sport", 0x47),
+    ("motorsport", 0x47),
+    ("water sport", 0x48),
+    ("winter sports", 0x49),
+    ("equestrian", 0x4A),
+    ("martial sports", 0x4B),
+    // Children's/Youth
+    ("children", 0x50),
+    ("kids", 0x50),
+    ("pre-school", 0x51),
+    ("educational", 0x54),
+    ("cartoon", 0x55),
+    ("animation", 0x55),
+    // Music/Ballet/Dance
+    ("music", 0x60),
+    ("rock", 0x61),
+    ("pop", 0x61),
+    ("classical music", 0x62),
+    ("folk", 0x63),
+    ("jazz", 0x64),
+    ("musical", 0x65),
+    ("opera", 0x65),
+    ("ballet", 0x66),
+    // Arts/Culture
+    ("arts", 0x70),
+    ("culture", 0x70),
+    ("performing arts", 0x71),
+    ("fine arts", 0x72),
+    ("religion", 0x73),
+    ("literature", 0x75),
+    ("cinema", 0x76),
+    ("fashion", 0x7B),
+    // Social/Political issues/Economics
+    ("politics", 0x80),
+    ("social", 0x80),
+    ("magazine", 0x81),
+    ("economics", 0x82),
+    ("biography", 0x83),
+    // Education/Science/Factual topics
+    ("education", 0x90),
+    ("science", 0x90),
+    ("nature", 0x91),
+    ("animals", 0x91),
+    ("technology", 0x92),
+    ("medicine", 0x93),
+    ("travel", 0x94),
+    ("languages", 0x97),
+    // Leisure hobbies
+    ("leisure", 0xA0),
+    ("tourism", 0xA1),
+    ("handicraft", 0xA2),
+    ("motoring", 0xA3),
+    ("health", 0xA4),
+    ("fitness", 0xA4),
+    ("cooking", 0xA5),
+    ("shopping", 0xA6),
+    ("gardening", 0xA7),
+];
+
+
+/// Mapping of the XMLTV categories to the DVB content
+#[derive(Debug)]
+pub struct GenreMap {
+    map: HashMap<String, u8>,
+}
+
+
+impl Default for GenreMap {
+    fn default() -> Self {
+        let map = DEFAULT_MAP.iter()
+            .map(|&(category, content)| (category.to_owned(), content))
+            .collect();
+
+        GenreMap {
+            map,
+        }
+    }
+}
+
+
+fn parse_content(s: &str) -> Option<u8> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u8::from_str_radix(&s[2 ..], 16).ok()
+    } else {
+        s.parse::<u8>().ok()
+    }
+}
+
+
+impl GenreMap {
+    /// Loads user defined mapping in addition to the default mapping.
+    /// Each line of the file contains category and content:
+    ///
+    /// ```text
+    /// # comment
+    /// Movie = 0x10
+    /// Talk Show = 0x33
+    /// ```
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let file = BufReader::new(File::open(path)?);
+
+        for (line_num, line) in file.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut pair = line.splitn(2, '=');
+            let category = pair.next().unwrap().trim();
+            let content = pair.next()
+                .and_then(|v| parse_content(v.trim()))
+                .ok_or(GenreError::Format(line_num + 1))?;
+
+            if category.is_empty() {
+                return Err(GenreError::Format(line_num + 1));
+            }
+
+            self.map.insert(category.to_lowercase(), content);
+        }
+
+        Ok(())
+    }
+
+    /// Returns DVB content for the XMLTV category
+    pub fn get(&self, category: &str) -> Option<u8> {
+        self.map.get(category.trim().to_lowercase().as_str()).cloned()
+    }
+}
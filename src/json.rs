@@ -0,0 +1,31 @@
+use std::fmt::Write;
+
+
+/// Appends string value in quotes with escaped special characters
+pub fn push_str(dst: &mut String, value: &str) {
+    dst.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => dst.push_str("\\\""),
+            '\\' => dst.push_str("\\\\"),
+            '\n' => dst.push_str("\\n"),
+            '\r' => dst.push_str("\\r"),
+            '\t' => dst.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(dst, "\\u{:04x}", c as u32);
+            }
+            c => dst.push(c),
+        }
+    }
+
+    dst.push('"');
+}
+
+
+/// Appends object key with separator
+#[inline]
+pub fn push_key(dst: &mut String, key: &str) {
+    push_str(dst, key);
+    dst.push(':');
+}
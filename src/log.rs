@@ -0,0 +1,226 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    fmt,
+    fs::{
+        File,
+        OpenOptions,
+    },
+    ffi::CString,
+    sync::{
+        Mutex,
+        atomic::{
+            AtomicU8,
+            AtomicUsize,
+            Ordering,
+        },
+    },
+};
+
+
+/// Message severity. Ordered from the most important
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 0,
+    Warning = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+
+impl Level {
+    /// Parses level name from config
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Level::Error),
+            "warning" => Some(Level::Warning),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Level::Error,
+            1 => Level::Warning,
+            2 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+
+    /// Returns level shifted by `-v` and `-q` command line flags
+    pub fn shift(self, verbosity: i32) -> Self {
+        let value = (self as i32 + verbosity).clamp(0, Level::Debug as i32);
+        Level::from_u8(value as u8)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warning => "WARNING",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    fn syslog_priority(self) -> libc::c_int {
+        match self {
+            Level::Error => libc::LOG_ERR,
+            Level::Warning => libc::LOG_WARNING,
+            Level::Info => libc::LOG_INFO,
+            Level::Debug => libc::LOG_DEBUG,
+        }
+    }
+}
+
+
+#[derive(Debug)]
+enum Target {
+    /// Standard error. Without timestamps and with priority prefix
+    /// if stderr is connected to the systemd journal
+    Stderr { journal: bool },
+    File(File),
+    Syslog,
+}
+
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static TARGET: Mutex<Option<Target>> = Mutex::new(None);
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Returns true if messages with `level` are written
+#[inline]
+pub fn is_enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+
+/// Returns number of error messages including not written
+#[inline]
+pub fn error_count() -> usize {
+    ERROR_COUNT.load(Ordering::Relaxed)
+}
+
+
+/// Defines log level and destination.
+/// `target` is `stderr`, `syslog`, or path to the log file
+pub fn init(level: Level, target: &str) -> io::Result<()> {
+    let target = match target {
+        "" | "stderr" => Target::Stderr {
+            journal: std::env::var_os("JOURNAL_STREAM").is_some(),
+        },
+        "syslog" => {
+            // identifier should live while syslog is used
+            static IDENT: &[u8] = b"eit-stream\0";
+            unsafe {
+                libc::openlog(IDENT.as_ptr() as *const libc::c_char, libc::LOG_PID, libc::LOG_DAEMON);
+            }
+            Target::Syslog
+        }
+        path => Target::File(OpenOptions::new().create(true).append(true).open(path)?),
+    };
+
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    if let Ok(mut v) = TARGET.lock() {
+        if let Some(Target::Syslog) = v.as_ref() {
+            if ! matches!(target, Target::Syslog) {
+                unsafe { libc::closelog() };
+            }
+        }
+        *v = Some(target);
+    }
+
+    Ok(())
+}
+
+
+/// Writes message. Used by the logging macros
+pub fn write(level: Level, context: Option<&str>, args: fmt::Arguments) {
+    if level == Level::Error {
+        ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    if ! is_enabled(level) {
+        return;
+    }
+
+    let message = match context {
+        Some(context) => format!("[{}] {}", context, args),
+        None => args.to_string(),
+    };
+
+    let mut target = match TARGET.lock() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    match target.as_mut() {
+        None | Some(Target::Stderr { journal: false }) => {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+            eprintln!("{} {}: {}", time, level.name(), message);
+        }
+        Some(Target::Stderr { journal: true }) => {
+            eprintln!("<{}>{}: {}", level.syslog_priority(), level.name(), message);
+        }
+        Some(Target::File(file)) => {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+            let _ = writeln!(file, "{} {}: {}", time, level.name(), message);
+        }
+        Some(Target::Syslog) => {
+            let message = CString::new(message.replace('\0', ""))
+                .unwrap_or_default();
+            unsafe {
+                libc::syslog(
+                    level.syslog_priority(),
+                    b"%s\0".as_ptr() as *const libc::c_char,
+                    message.as_ptr());
+            }
+        }
+    }
+}
+
+
+/// Writes error message.
+/// Context defined with `ctx: value,` before format string
+macro_rules! error {
+    (ctx: $ctx:expr, $($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Error, Some($ctx), format_args!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Error, None, format_args!($($arg)+))
+    };
+}
+
+
+macro_rules! warning {
+    (ctx: $ctx:expr, $($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Warning, Some($ctx), format_args!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Warning, None, format_args!($($arg)+))
+    };
+}
+
+
+macro_rules! info {
+    (ctx: $ctx:expr, $($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Info, Some($ctx), format_args!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Info, None, format_args!($($arg)+))
+    };
+}
+
+
+macro_rules! debug {
+    (ctx: $ctx:expr, $($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Debug, Some($ctx), format_args!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::log::write($crate::log::Level::Debug, None, format_args!($($arg)+))
+    };
+}
@@ -1,18 +1,59 @@
 #[macro_use]
 extern crate error_rules;
 
+#[macro_use]
+mod log;
+mod section;
+mod event;
+mod genre;
+mod rating;
+mod discovery;
+mod xmltv;
+mod state;
+mod scheduler;
+mod clock;
+mod json;
+mod status;
+mod check;
+mod text;
+mod dump;
+mod schedule;
+mod analyze;
+
 use {
     std::{
         io::{
             self,
             BufWriter,
+            Read,
             Write,
         },
         time,
         thread,
         cmp,
-        fs::File,
-        collections::HashMap,
+        net::{
+            self,
+            Ipv4Addr,
+            SocketAddrV4,
+        },
+        os::unix::fs::OpenOptionsExt,
+        fs::{
+            File,
+            OpenOptions,
+        },
+        path::PathBuf,
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        sync::{
+            Arc,
+            Mutex,
+            atomic::{
+                AtomicBool,
+                Ordering,
+            },
+        },
     },
 
     epg::{
@@ -24,9 +65,13 @@ use {
         ts,
         psi::{
             self,
+            Psi,
             PsiDemux,
             Eit,
             EitItem,
+            Sdt,
+            SdtItem,
+            Desc48,
             Tdt,
             Tot,
             Desc58,
@@ -35,8 +80,59 @@ use {
         textcode,
     },
 
+    chrono::{
+        TimeZone,
+        Offset,
+    },
+    chrono_tz::Tz,
+
     udp::UdpSocket,
 
+    crate::{
+        event::EventOptions,
+        discovery::{
+            Discovery,
+            TransportStream,
+        },
+        xmltv::{
+            Remote,
+            XmltvError,
+        },
+        state::{
+            State,
+            ServiceState,
+            StreamState,
+        },
+        scheduler::{
+            Scheduler,
+            Table,
+            Intervals,
+        },
+        clock::{
+            Clock,
+            RateMeter,
+        },
+        status::{
+            Status,
+            ServiceStatus,
+            StreamStatus,
+            XmltvStatus,
+        },
+        genre::{
+            GenreMap,
+            GenreError,
+        },
+        check::Coverage,
+        analyze::Analyzer,
+        section::SectionReader,
+        schedule::{
+            ScheduleTables,
+            SEGMENT_DURATION,
+            SEGMENTS_PER_DAY,
+            SECTIONS_PER_SEGMENT,
+        },
+    },
+
     config::{
         Config,
         Schema,
@@ -54,10 +150,26 @@ enum AppError {
     Epg(EpgError),
     #[error_from]
     Config(ConfigError),
+    #[error_from]
+    Genre(GenreError),
+    #[error_from]
+    Xmltv(XmltvError),
     #[error_kind("unknown output format")]
     UnknownOutput,
     #[error_kind("output not defined")]
     MissingOutput,
+    #[error_kind("invalid RTP option: {}", 0)]
+    RtpOption(String),
+    #[error_kind("unknown time zone: {}", 0)]
+    UnknownTimezone(String),
+    #[error_kind("invalid input: {}", 0)]
+    InvalidInput(String),
+    #[error_kind("different inputs for output: {}", 0)]
+    InputConflict(String),
+    #[error_kind("failed to open status listener {}: {}", 0, 1)]
+    StatusListen(String, io::Error),
+    #[error_kind("failed to open log {}: {}", 0, 1)]
+    LogOpen(String, io::Error),
 }
 
 
@@ -66,6 +178,31 @@ type Result<T> = std::result::Result<T, AppError>;
 
 const BLOCK_SIZE: usize = ts::PACKET_SIZE * 7;
 const IDLE_DELAY: time::Duration = time::Duration::from_secs(1);
+/// Output clock may lag behind by this time before restart.
+/// Lost time is caught up with short burst
+const CLOCK_MAX_LAG: time::Duration = time::Duration::from_millis(100);
+/// Interval to report measured output rate if `rate-report` not defined
+const RATE_REPORT_INTERVAL: u64 = 300;
+/// Interval to print report for the live input in analyze mode
+const ANALYZE_REPORT_INTERVAL: time::Duration = time::Duration::from_secs(10);
+/// Interval to update snapshot for the status listener
+const STATUS_UPDATE_INTERVAL: time::Duration = time::Duration::from_secs(1);
+/// Interval to recalculate local time offset for time zones
+const TZ_UPDATE_INTERVAL: u64 = 3600;
+/// How many days to look up for the next time zone offset change
+const TZ_LOOKUP_DAYS: usize = 400;
+/// Interval to read input in remux mode
+const INPUT_DELAY: time::Duration = time::Duration::from_millis(1);
+/// Maximum amount of data to read from input at once
+const INPUT_READ_LIMIT: usize = 1024 * 1024;
+const NULL_PID: u16 = 0x1FFF;
+/// Interval to check XMLTV modification time and reload interval
+const XMLTV_CHECK_INTERVAL: time::Duration = time::Duration::from_secs(60);
+/// Reload interval for remote XMLTV if `xmltv-reload` not defined
+const XMLTV_REMOTE_RELOAD: time::Duration = time::Duration::from_secs(3600);
+
+/// Following event gets status "starts in a few seconds" before this interval
+const STARTING_INTERVAL: u64 = 30;
 
 
 include!(concat!(env!("OUT_DIR"), "/build.rs"));
@@ -77,15 +214,32 @@ fn version() {
 
 
 fn usage(program: &str) {
-    println!(r#"Usage: {} CONFIG
+    println!(r#"Usage: {0} [OPTIONS] CONFIG
+       {0} analyze INPUT
 
 OPTIONS:
-    -v, --version       Version information
+    -V, --version       Version information
     -h, --help          Print this text
     -H                  Configuration file format
+    -v                  Increase log verbosity. Could be repeated
+    -q                  Decrease log verbosity. Could be repeated
+    --check             Check configuration and XMLTV coverage and exit.
+                        Exit status is not zero if any error found
+    --dump FORMAT       Print generated EIT instead of streaming and exit.
+                        FORMAT: text or json
 
 CONFIG:
     Path to configuration file
+
+ANALYZE:
+    Reads transport stream and reports EIT and TDT/TOT errors,
+    repetition intervals, version changes, and TDT/TOT clock accuracy.
+    INPUT: udp://[ifaddr@]group:port, file://path, or path to the file.
+    Live input analyzed until SIGINT or SIGTERM
+
+SIGNALS:
+    SIGHUP              Reload configuration file
+    SIGINT, SIGTERM     Save state and exit
 "#, program);
 }
 
@@ -95,10 +249,90 @@ fn parse_offset(offset: &str) -> i32 {
 }
 
 
+/// RTP header size
+const RTP_HEADER_SIZE: usize = 12;
+/// RTP payload type for MPEG-TS (RFC 3551)
+const RTP_PAYLOAD_MP2T: u8 = 33;
+/// RTP timestamp clock rate
+const RTP_CLOCK_RATE: u64 = 90_000;
+
+
+/// RTP/MP2T output. Wraps each datagram into RTP header
+#[derive(Debug)]
+struct RtpOutput {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    sequence: u16,
+    ssrc: u32,
+    start: time::Instant,
+}
+
+
+impl RtpOutput {
+    /// Opens RTP output. Address format: `239.255.1.1:10000?ssrc=1234`.
+    /// If SSRC is not defined then random value is used
+    fn open(addr: &str) -> Result<Self> {
+        let mut addr = addr.splitn(2, '?');
+        let socket_addr = addr.next().unwrap();
+
+        let mut ssrc = None;
+        if let Some(query) = addr.next() {
+            for option in query.split('&') {
+                let mut option = option.splitn(2, '=');
+                match (option.next().unwrap(), option.next()) {
+                    ("ssrc", Some(v)) => {
+                        let v = v.parse::<u32>()
+                            .map_err(|_| AppError::RtpOption(v.to_owned()))?;
+                        ssrc = Some(v);
+                    }
+                    (v, _) => return Err(AppError::RtpOption(v.to_owned())),
+                }
+            }
+        }
+
+        let ssrc = ssrc.unwrap_or_else(|| {
+            let nanos = time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH).unwrap()
+                .subsec_nanos();
+            nanos ^ std::process::id().rotate_left(16)
+        });
+
+        Ok(RtpOutput {
+            socket: UdpSocket::open(socket_addr)?,
+            buffer: Vec::with_capacity(RTP_HEADER_SIZE + BLOCK_SIZE),
+            sequence: 0,
+            ssrc,
+            start: time::Instant::now(),
+        })
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let elapsed = self.start.elapsed();
+        let timestamp = elapsed.as_secs() * RTP_CLOCK_RATE +
+            u64::from(elapsed.subsec_nanos()) * RTP_CLOCK_RATE / 1_000_000_000;
+
+        self.buffer.clear();
+        // version 2, no padding, no extension, no CSRC
+        self.buffer.push(0x80);
+        self.buffer.push(RTP_PAYLOAD_MP2T);
+        self.buffer.extend_from_slice(&self.sequence.to_be_bytes());
+        self.buffer.extend_from_slice(&(timestamp as u32).to_be_bytes());
+        self.buffer.extend_from_slice(&self.ssrc.to_be_bytes());
+        self.buffer.extend_from_slice(data);
+
+        self.sequence = self.sequence.wrapping_add(1);
+
+        self.socket.sendto(&self.buffer)?;
+        Ok(())
+    }
+}
+
+
 #[derive(Debug)]
 enum Output {
     None,
     Udp(UdpSocket),
+    Rtp(RtpOutput),
     File(BufWriter<File>),
 }
 
@@ -119,6 +353,10 @@ impl Output {
                 let s = UdpSocket::open(dst[1])?;
                 Ok(Output::Udp(s))
             }
+            "rtp" => {
+                let s = RtpOutput::open(dst[1])?;
+                Ok(Output::Rtp(s))
+            }
             "file" => {
                 let file = File::create(dst[1])?;
                 Ok(Output::File(BufWriter::new(file)))
@@ -132,6 +370,9 @@ impl Output {
             Output::Udp(udp) => {
                 udp.sendto(data)?;
             }
+            Output::Rtp(rtp) => {
+                rtp.send(data)?;
+            }
             Output::File(file) => {
                 file.write_all(data)?;
             }
@@ -142,11 +383,220 @@ impl Output {
 }
 
 
+/// Source of the transport stream for remux mode
+#[derive(Debug)]
+enum Input {
+    Udp(net::UdpSocket),
+    File(File),
+}
+
+
+/// Opens UDP input. Address format: `239.255.1.1:10000`.
+/// Local interface address could be defined for multicast: `192.168.1.10@239.255.1.1:10000`
+fn open_udp_input(addr: &str) -> Result<net::UdpSocket> {
+    let invalid = || AppError::InvalidInput(addr.to_owned());
+
+    let (ifaddr, group) = match addr.find('@') {
+        Some(pos) => (&addr[.. pos], &addr[pos + 1 ..]),
+        None => ("0.0.0.0", addr),
+    };
+    let ifaddr: Ipv4Addr = ifaddr.parse().map_err(|_| invalid())?;
+    let group: SocketAddrV4 = group.parse().map_err(|_| invalid())?;
+
+    let socket = net::UdpSocket::bind(group)?;
+    if group.ip().is_multicast() {
+        socket.join_multicast_v4(group.ip(), &ifaddr)?;
+    }
+    socket.set_nonblocking(true)?;
+
+    Ok(socket)
+}
+
+
+impl Input {
+    /// Opens input. Supported formats: `udp://239.255.1.1:10000` and
+    /// `file:///path/to/fifo`. File opened in non-blocking mode and
+    /// intended for pipes
+    fn open(addr: &str) -> Result<Self> {
+        let mut dst = addr.splitn(2, "://");
+        match (dst.next().unwrap(), dst.next()) {
+            ("udp", Some(v)) => {
+                let s = open_udp_input(v)?;
+                Ok(Input::Udp(s))
+            }
+            ("file", Some(v)) => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(v)?;
+                Ok(Input::File(file))
+            }
+            _ => Err(AppError::InvalidInput(addr.to_owned())),
+        }
+    }
+
+    /// Reads available data. Returns 0 if no data
+    fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let result = match self {
+            Input::Udp(udp) => udp.recv(buffer),
+            Input::File(file) => file.read(buffer),
+        };
+
+        match result {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+            v => v,
+        }
+    }
+}
+
+
+#[derive(Debug)]
+struct OutputItem {
+    addr: String,
+    output: Output,
+    /// Last send failed. Used to report errors once
+    failed: bool,
+}
+
+
+impl OutputItem {
+    fn open(addr: &str) -> Result<Self> {
+        Ok(OutputItem {
+            addr: addr.to_owned(),
+            output: Output::open(addr)?,
+            failed: false,
+        })
+    }
+}
+
+
+/// Splits comma-separated list of addresses. Skips duplicates
+fn parse_addr_list(addr_list: &str) -> Vec<&str> {
+    let mut list: Vec<&str> = Vec::new();
+    for addr in addr_list.split(',').map(str::trim) {
+        if ! addr.is_empty() && ! list.contains(&addr) {
+            list.push(addr);
+        }
+    }
+    list
+}
+
+
+/// List of the output destinations. Each block sent to all destinations
+#[derive(Debug, Default)]
+struct OutputList {
+    list: Vec<OutputItem>,
+}
+
+
+impl OutputList {
+    /// Opens outputs defined in the comma-separated list of addresses.
+    /// Already opened outputs with same address are taken from `pool`
+    fn open(addr_list: &str, pool: &mut Vec<OutputItem>) -> Result<Self> {
+        let mut list = Vec::new();
+
+        for addr in parse_addr_list(addr_list) {
+            let item = match pool.iter().position(|v| v.addr == addr) {
+                Some(pos) => pool.remove(pos),
+                None => OutputItem::open(addr)?,
+            };
+            list.push(item);
+        }
+
+        if list.is_empty() {
+            return Err(AppError::MissingOutput);
+        }
+
+        Ok(OutputList {
+            list,
+        })
+    }
+
+    /// Sends data to all outputs.
+    /// Failure in one destination doesn't stop others
+    fn send(&mut self, data: &[u8]) {
+        for item in &mut self.list {
+            match item.output.send(data) {
+                Ok(_) => {
+                    if item.failed {
+                        item.failed = false;
+                        info!(ctx: &item.addr, "output restored");
+                    }
+                }
+                Err(e) => {
+                    if ! item.failed {
+                        item.failed = true;
+                        error!(ctx: &item.addr, "failed to send [{}]", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+/// Splits offset in minutes into absolute value and polarity
+fn split_offset(offset: i32) -> (u16, u8) {
+    if offset >= 0 {
+        (offset as u16, 0)
+    } else {
+        ((-offset) as u16, 1)
+    }
+}
+
+
+/// Returns offset from UTC in minutes for the time zone at given time
+fn tz_offset(tz: Tz, timestamp: u64) -> i32 {
+    tz.timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|dt| dt.offset().fix().local_minus_utc() / 60)
+        .unwrap_or(0)
+}
+
+
+/// Looks for the next offset change in the time zone.
+/// Returns time of change and offset in minutes after the change
+fn tz_next_change(tz: Tz, timestamp: u64) -> Option<(u64, i32)> {
+    const DAY: u64 = 86400;
+
+    let offset = tz_offset(tz, timestamp);
+
+    let mut prev = timestamp;
+    for _ in 0 .. TZ_LOOKUP_DAYS {
+        let next = prev + DAY;
+        let next_offset = tz_offset(tz, next);
+        if next_offset == offset {
+            prev = next;
+            continue;
+        }
+
+        // change is between prev and next. find the exact second
+        let (mut lo, mut hi) = (prev, next);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if tz_offset(tz, mid) == offset {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        return Some((hi, next_offset));
+    }
+
+    None
+}
+
+
 #[derive(Debug, Default)]
 struct TdtTot {
-    cc: u8,
     tdt: Tdt,
     tot: Tot,
+    /// Time zone for each item in the local time offset descriptor.
+    /// None for items with fixed offset
+    zone_list: Vec<Option<Tz>>,
+    /// Time to check time zones
+    next_update: u64,
 }
 
 
@@ -154,18 +604,21 @@ impl TdtTot {
     fn parse_config(&mut self, config: &Config) -> Result<()> {
         let country = config.get("country").unwrap_or("   ");
 
-        let (offset, offset_polarity) = {
-            let offset = config.get("offset")
-                .map(parse_offset)
-                .unwrap_or(0);
-
-            if offset >= 0 {
-                (offset as u16, 0)
-            } else {
-                ((-offset) as u16, 1)
-            }
+        let timezone: Option<&str> = config.get("timezone");
+        let zone = match timezone {
+            Some(v) => match v.parse::<Tz>() {
+                Ok(v) => Some(v),
+                Err(_) => return Err(AppError::UnknownTimezone(v.to_owned())),
+            },
+            None => None,
         };
 
+        let (offset, offset_polarity) = split_offset(
+            config.get("offset")
+                .map(parse_offset)
+                .unwrap_or(0)
+        );
+
         if self.tot.descriptors.is_empty() {
             self.tot.descriptors.push(Desc58::default());
         }
@@ -183,21 +636,213 @@ impl TdtTot {
             next_offset: offset,
         });
 
+        self.zone_list.push(zone);
+        // time zones will be applied on next update
+        self.next_update = 0;
+
         Ok(())
     }
 
+    /// Updates local time offset descriptor for items with time zone
+    fn update_zones(&mut self, timestamp: u64) {
+        if timestamp < self.next_update {
+            return;
+        }
+
+        let desc = self.tot.descriptors
+            .get_mut(0).unwrap()
+            .downcast_mut::<Desc58>();
+
+        self.next_update = timestamp + TZ_UPDATE_INTERVAL;
+
+        for (item, zone) in desc.items.iter_mut().zip(&self.zone_list) {
+            let tz = match zone {
+                Some(v) => *v,
+                None => continue,
+            };
+
+            let (offset, offset_polarity) = split_offset(tz_offset(tz, timestamp));
+            item.offset_polarity = offset_polarity;
+            item.offset = offset;
+
+            match tz_next_change(tz, timestamp) {
+                Some((time_of_change, next_offset)) => {
+                    // polarity is shared with next_offset. zero offset has no sign
+                    // (Atlantic/Azores: 0 in summer, -60 in winter)
+                    let (next_offset, next_polarity) = split_offset(next_offset);
+                    if offset == 0 {
+                        item.offset_polarity = next_polarity;
+                    }
+                    item.time_of_change = time_of_change;
+                    item.next_offset = next_offset;
+                    self.next_update = cmp::min(self.next_update, time_of_change);
+                }
+                None => {
+                    item.time_of_change = 0;
+                    item.next_offset = offset;
+                }
+            }
+        }
+    }
+
     fn update(&mut self) {
         let timestamp = time::SystemTime::now()
             .duration_since(time::UNIX_EPOCH).unwrap()
             .as_secs();
         self.tdt.time = timestamp;
         self.tot.time = timestamp;
+        self.update_zones(timestamp);
     }
 
-    fn demux(&mut self, dst: &mut Vec<u8>) {
+    fn psi_list_assemble(&mut self) -> Vec<Psi> {
         self.update();
-        self.tdt.demux(psi::TDT_PID, &mut self.cc, dst);
-        self.tot.demux(psi::TOT_PID, &mut self.cc, dst);
+        let mut psi_list = self.tdt.psi_list_assemble();
+        psi_list.append(&mut self.tot.psi_list_assemble());
+        psi_list
+    }
+}
+
+
+/// XMLTV source
+#[derive(Default, Debug)]
+struct EpgItem {
+    path: String,
+    epg: Epg,
+    /// File modification time. None for remote sources
+    mtime: Option<time::SystemTime>,
+    load_time: Option<time::Instant>,
+    /// Download and cache options for http/https sources
+    remote: Option<Remote>,
+    /// Unix time of the last successful load
+    update_time: u64,
+    /// Error of the last load. Empty if XMLTV loaded successfully
+    error: String,
+}
+
+
+impl EpgItem {
+    fn get_mtime(path: &str) -> Option<time::SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if let Some(remote) = &self.remote {
+            // cached copy is used until background download is completed.
+            // see Instance::reload_xmltv
+            if remote.is_cached() {
+                match self.apply_fetch(Ok(None)) {
+                    Ok(_) => {
+                        if let Some(remote) = &mut self.remote {
+                            remote.spawn();
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        warning!(ctx: &self.path, "failed to load cached XMLTV [{}]", e);
+                        self.load_time = None;
+                    }
+                }
+            }
+        }
+
+        if let Some(remote) = &self.remote {
+            let result = remote.fetch();
+            self.apply_fetch(result)?;
+            return Ok(());
+        }
+
+        let mtime = Self::get_mtime(&self.path);
+
+        let mut epg = Epg::default();
+        epg.load(&self.path)?;
+
+        self.epg = epg;
+        self.mtime = mtime;
+        self.load_time = Some(time::Instant::now());
+
+        Ok(())
+    }
+
+    /// Loads downloaded XMLTV and replaces cached copy.
+    /// If download failed then cached copy is used on first load.
+    /// Returns true if XMLTV has been changed
+    fn apply_fetch(&mut self, result: xmltv::Result<Option<xmltv::Meta>>) -> Result<bool> {
+        let remote = match self.remote.as_mut() {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        let is_loaded = self.load_time.is_some();
+        self.load_time = Some(time::Instant::now());
+
+        let error = match result {
+            Ok(Some(meta)) => {
+                let mut epg = Epg::default();
+                match epg.load(remote.tmp_path().to_str().unwrap_or("")) {
+                    Ok(_) => {
+                        remote.commit(meta)?;
+                        self.epg = epg;
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        remote.discard();
+                        AppError::from(e)
+                    }
+                }
+            }
+            // not modified
+            Ok(None) if is_loaded => return Ok(false),
+            Ok(None) => {
+                let mut epg = Epg::default();
+                epg.load(remote.path().to_str().unwrap_or(""))?;
+                self.epg = epg;
+                return Ok(true);
+            }
+            Err(e) => AppError::from(e),
+        };
+
+        if is_loaded || ! remote.is_cached() {
+            return Err(error);
+        }
+
+        error!(ctx: &self.path, "failed to download XMLTV [{}]. Cached copy is used", error);
+
+        let mut epg = Epg::default();
+        epg.load(remote.path().to_str().unwrap_or(""))?;
+        self.epg = epg;
+
+        Ok(true)
+    }
+
+    /// Keeps result of the last load for the status listener
+    fn set_status<T>(&mut self, result: &Result<T>) {
+        match result {
+            Ok(_) => {
+                self.update_time = chrono::Utc::now().timestamp() as u64;
+                self.error.clear();
+            }
+            Err(e) => self.error = e.to_string(),
+        }
+    }
+
+    /// Returns true if XMLTV file has been modified
+    /// or reload interval is expired
+    fn is_expired(&self, interval: Option<time::Duration>) -> bool {
+        if let Some(mtime) = self.mtime {
+            if Self::get_mtime(&self.path) != Some(mtime) {
+                return true;
+            }
+        }
+
+        let interval = match &self.remote {
+            Some(_) => interval.or(Some(XMLTV_REMOTE_RELOAD)),
+            None => interval,
+        };
+
+        match (interval, self.load_time) {
+            (Some(interval), Some(load_time)) => load_time.elapsed() >= interval,
+            _ => false,
+        }
     }
 }
 
@@ -205,205 +850,1859 @@ impl TdtTot {
 #[derive(Default, Debug)]
 struct Instance {
     epg_item_id: usize,
-    epg_list: Vec<Epg>,
+    epg_list: Vec<EpgItem>,
     epg_map: HashMap<String, usize>,
-
-    output: Output,
-
-    multiplex: Multiplex,
+    /// XMLTV sources of the running instance by path. Defined on reload.
+    /// Sources are taken from the running instance instead of loading
+    /// again, new remote sources are downloaded in background
+    xmltv_prev: Option<HashMap<String, usize>>,
+    xmltv_reload: Option<time::Duration>,
+    /// Directory to keep downloaded XMLTV
+    xmltv_cache: PathBuf,
+    /// Path to the state file. Empty if disabled
+    state_path: String,
+    /// Title of the filler event for gaps. Empty if disabled
+    filler: String,
+    /// Maximum repetition intervals of the tables
+    intervals: Intervals,
+    /// State loaded on start
+    state: State,
+
+    output_addr: String,
+    stream_list: Vec<Stream>,
+
+    multiplex_list: Vec<Multiplex>,
     service_list: Vec<Service>,
+    /// Services skipped on config parsing: service identifier and reason.
+    /// Reported in the check mode
+    skipped_list: Vec<(String, &'static str)>,
 
     onid: u16,
     codepage: u8,
     eit_days: usize,
     eit_rate: Option<usize>,
+    cbr: Option<bool>,
+    /// Interval to report measured output rate. None if disabled
+    rate_report: Option<time::Duration>,
 
     utc_offset: i32,
     country: String,
+    event_options: EventOptions,
 
     tdt_tot: Option<TdtTot>,
 }
 
 
 impl Instance {
-    fn open_xmltv(&mut self, config: &Config, def: usize) -> Result<Option<usize>> {
-        let path = match config.get("xmltv") {
-            Some(v) => v,
-            None => return Ok(Some(def)),
+    #[inline]
+    fn open(config: &Config) -> Result<Self> {
+        Self::open_with(config, None)
+    }
+
+    /// Opens instance with configuration.
+    /// `xmltv_prev` is a map of the XMLTV sources loaded by running instance
+    fn open_with(config: &Config, xmltv_prev: Option<HashMap<String, usize>>) -> Result<Self> {
+        let mut instance = Instance {
+            onid: config.get("onid").unwrap_or(1),
+            codepage: config.get("codepage").unwrap_or(0),
+            eit_days: config.get("eit-days").unwrap_or(3),
+            eit_rate: config.get("eit-rate"),
+            cbr: config.get("cbr"),
+            rate_report: Some(config.get("rate-report").unwrap_or(RATE_REPORT_INTERVAL))
+                .filter(|&v| v != 0)
+                .map(time::Duration::from_secs),
+            utc_offset: config.get("utc-offset").map(parse_offset).unwrap_or(0),
+            country: config.get("country").unwrap_or("   ").to_owned(),
+            xmltv_reload: config.get("xmltv-reload")
+                .filter(|&v: &u64| v != 0)
+                .map(|v| time::Duration::from_secs(v * 60)),
+            xmltv_cache: config.get("xmltv-cache")
+                .map(|v: &str| PathBuf::from(v))
+                .unwrap_or_else(std::env::temp_dir),
+            xmltv_prev,
+            state_path: config.get("state").unwrap_or("").to_owned(),
+            filler: config.get("filler").unwrap_or("").to_owned(),
+            event_options: EventOptions {
+                short_text: config.get("short-text").unwrap_or(false),
+                genre_map: GenreMap::default(),
+            },
+            ..Default::default()
         };
 
-        if let Some(&v) = self.epg_map.get(path) {
-            return Ok(Some(v));
+        if let Some(path) = config.get("genre-map") {
+            instance.event_options.genre_map.load(path)?;
         }
 
-        let mut epg = Epg::default();
-        match epg.load(path) {
-            Ok(_) => {},
-            Err(e) => {
-                eprintln!("Error: failed to load XMLTV from {} [{}]", path, e);
-                return Ok(None);
+        let intervals = &mut instance.intervals;
+        for (key, value) in &mut [
+            ("pf-interval", &mut intervals.present),
+            ("pf-other-interval", &mut intervals.present_other),
+            ("schedule-interval", &mut intervals.schedule),
+            ("schedule-later-interval", &mut intervals.schedule_later),
+            ("sdt-interval", &mut intervals.sdt),
+            ("sdt-other-interval", &mut intervals.sdt_other),
+            ("tdt-tot-interval", &mut intervals.tdt_tot),
+        ] {
+            if let Some(v) = config.get(key) {
+                **value = time::Duration::from_secs(v);
             }
+        }
+
+        match instance.open_xmltv(config, usize::max_value())? {
+            Some(v) => instance.epg_item_id = v,
+            None => instance.epg_item_id = usize::max_value(),
         };
-        let v = self.epg_list.len();
-        self.epg_list.push(epg);
-        self.epg_map.insert(path.to_owned(), v);
 
-        Ok(Some(v))
-    }
+        if let Some(v) = config.get("output") {
+            instance.output_addr.push_str(v);
+        }
 
-    fn open_output(&mut self, addr: &str) -> Result<()> {
-        self.output = Output::open(addr)?;
-        Ok(())
+        for m in config.iter() {
+            match m.get_name() {
+                "multiplex" => instance.parse_config(m)?,
+                "tdt-tot" => instance.parse_tdt_tot(m)?,
+                _ => {}
+            }
+        }
+
+        instance.link_streams()?;
+
+        Ok(instance)
     }
 
-    fn parse_config(&mut self, config: &Config) -> Result<()> {
-        if ! config.get("enable").unwrap_or(true) {
-            return Ok(())
+    /// Applies new configuration.
+    /// Keeps output, continuity counters, table versions and loaded XMLTV
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        let mut instance = Instance::open_with(config, Some(self.epg_map.clone()))?;
+
+        // open new destinations and inputs before running streams will be changed
+        let mut pool = Vec::new();
+        let mut input_pool = Vec::new();
+        for stream in &instance.stream_list {
+            for addr in parse_addr_list(&stream.output_addr) {
+                let is_opened = self.stream_list.iter()
+                    .flat_map(|s| s.output.list.iter())
+                    .chain(pool.iter())
+                    .any(|v| v.addr == addr);
+                if ! is_opened {
+                    pool.push(OutputItem::open(addr)?);
+                }
+            }
+
+            let addr = &stream.input_addr;
+            if ! addr.is_empty() {
+                let is_opened = self.stream_list.iter()
+                    .filter(|s| s.input.is_some())
+                    .map(|s| &s.input_addr)
+                    .chain(input_pool.iter().map(|(a, _)| a))
+                    .any(|a| a == addr);
+                if ! is_opened {
+                    input_pool.push((addr.clone(), Input::open(addr)?));
+                }
+            }
+        }
+
+        // configuration validated and all addresses opened above.
+        // running streams are not changed if reload fails before this point
+        for stream in &mut self.stream_list {
+            pool.append(&mut stream.output.list);
+            if let Some(input) = stream.input.take() {
+                input_pool.push((stream.input_addr.clone(), input));
+            }
         }
 
-        self.multiplex.onid = config.get("onid")
-            .unwrap_or(self.onid);
-        self.multiplex.codepage = config.get("codepage")
-            .unwrap_or(self.codepage);
-        self.multiplex.utc_offset = config.get("utc-offset")
-            .map(parse_offset)
-            .unwrap_or(self.utc_offset);
-        self.multiplex.tsid = config.get("tsid")
-            .unwrap_or(1);
+        for stream in &mut instance.stream_list {
+            stream.output = OutputList::open(&stream.output_addr, &mut pool)?;
+            stream.open_input(&mut input_pool)?;
 
-        match self.open_xmltv(config, self.epg_item_id)? {
-            Some(v) => self.multiplex.epg_item_id = v,
-            None => return Ok(()),
-        };
+            let prev = self.stream_list.iter_mut().find(|s| {
+                s.output_addr == stream.output_addr
+            });
 
-        for s in config.iter() {
-            if s.get_name() != "service" {
-                continue;
+            if let Some(prev) = prev {
+                stream.keep(prev);
             }
+        }
 
-            let mut service = Service {
-                onid: self.multiplex.onid,
-                tsid: self.multiplex.tsid,
+        // loaded XMLTV sources are kept with background downloads in progress
+        if let Some(xmltv_prev) = instance.xmltv_prev.take() {
+            for epg_item in &mut instance.epg_list {
+                match xmltv_prev.get(&epg_item.path) {
+                    Some(&v) => *epg_item = std::mem::take(&mut self.epg_list[v]),
+                    None => {
+                        if let Some(remote) = &mut epg_item.remote {
+                            remote.spawn();
+                        }
+                    }
+                }
+            }
+        }
+
+        instance.state = std::mem::take(&mut self.state);
+        instance.apply_discovery();
+
+        for multiplex in &mut instance.multiplex_list {
+            let prev = self.multiplex_list.iter_mut().find(|m| {
+                m.onid == multiplex.onid && m.tsid == multiplex.tsid
+            });
+
+            if let Some(prev) = prev {
+                multiplex.sdt = std::mem::take(&mut prev.sdt);
+            }
+        }
+
+        for service in &mut instance.service_list {
+            let prev = self.service_list.iter_mut().find(|s| {
+                s.onid == service.onid && s.tsid == service.tsid && s.pnr == service.pnr
+            });
+
+            // versions will be changed on load if events are changed
+            if let Some(prev) = prev {
+                service.present = std::mem::take(&mut prev.present);
+                service.following = prev.following.take();
+                service.schedule = std::mem::take(&mut prev.schedule);
+                service.schedule_tables = std::mem::take(&mut prev.schedule_tables);
+                service.title_map = std::mem::take(&mut prev.title_map);
+                service.last_stop = prev.last_stop;
+                service.sections_sent = prev.sections_sent;
+                service.bytes_sent = prev.bytes_sent;
+            }
+        }
+
+        instance.load();
+
+        *self = instance;
+        Ok(())
+    }
+
+    /// Restores table versions and continuity counters saved on previous run
+    fn restore_state(&mut self) {
+        if self.state_path.is_empty() {
+            return;
+        }
+
+        self.state = match State::load(&self.state_path) {
+            Ok(v) => v,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warning!("failed to load state from {} [{}]", &self.state_path, e);
+                return;
+            }
+        };
+
+        for service in &mut self.service_list {
+            service.restore(&self.state);
+        }
+
+        for multiplex in &mut self.multiplex_list {
+            if let Some(&version) = self.state.sdt_map.get(&(multiplex.onid, multiplex.tsid)) {
+                multiplex.sdt.version = (version + 1) % 32;
+            }
+        }
+
+        for stream in &mut self.stream_list {
+            if let Some(v) = self.state.stream_map.get(&stream.output_addr) {
+                stream.eit_cc = v.eit_cc;
+                stream.sdt_cc = v.sdt_cc;
+                stream.tdt_cc = v.tdt_cc;
+            }
+        }
+    }
+
+    /// Saves table versions and continuity counters
+    fn save_state(&self) {
+        if self.state_path.is_empty() {
+            return;
+        }
+
+        let mut state = State::default();
+
+        for service in &self.service_list {
+            if service.pnr != 0 {
+                state.service_map.insert((service.onid, service.tsid, service.pnr), ServiceState {
+                    present_version: service.present.version,
+                    schedule_version: service.schedule_tables.version_list,
+                });
+            }
+        }
+
+        for multiplex in &self.multiplex_list {
+            state.sdt_map.insert((multiplex.onid, multiplex.tsid), multiplex.sdt.version);
+        }
+
+        for stream in &self.stream_list {
+            state.stream_map.insert(stream.output_addr.clone(), StreamState {
+                eit_cc: stream.eit_cc,
+                sdt_cc: stream.sdt_cc,
+                tdt_cc: stream.tdt_cc,
+            });
+        }
+
+        if let Err(e) = state.save(&self.state_path) {
+            error!("failed to save state to {} [{}]", &self.state_path, e);
+        }
+    }
+
+    /// Prints XMLTV coverage of the EIT schedule for all services.
+    /// Returns false if any service has no events
+    fn check(&self) -> bool {
+        let current_time = chrono::Utc::now().timestamp() as u64;
+        let last_time = current_time + self.eit_days as u64 * 86400;
+
+        let mut error_count = 0;
+        let mut warning_count = 0;
+
+        for (context, reason) in &self.skipped_list {
+            println!("service {}", context);
+            println!("    error: {}", reason);
+            error_count += 1;
+        }
+
+        for service in &self.service_list {
+            println!("service {}", service.context());
+
+            let epg_item = match self.epg_list.get(service.epg_item_id) {
+                Some(v) => v,
+                None => {
+                    println!("    error: xmltv not loaded");
+                    error_count += 1;
+                    continue;
+                }
+            };
+            println!("    xmltv: {}", &epg_item.path);
+
+            let channel = match epg_item.epg.channels.get(&service.xmltv_id) {
+                Some(v) => v,
+                None => {
+                    println!("    error: channel not found in XMLTV");
+                    error_count += 1;
+                    continue;
+                }
+            };
+
+            let offset = i64::from(service.utc_offset) * 60;
+            let event_list = channel.events.iter().map(|e| {
+                ((e.start as i64 - offset) as u64, (e.stop as i64 - offset) as u64)
+            });
+            let coverage = Coverage::new(event_list, current_time, last_time);
+
+            println!("    events: {} in {} days", coverage.events, self.eit_days);
+            let (first, last) = match (coverage.first, coverage.last) {
+                (Some(first), Some(last)) => (first, last),
+                _ => {
+                    println!("    error: no events");
+                    error_count += 1;
+                    continue;
+                }
+            };
+
+            println!("    first: {}", format_time(first));
+            println!("    last: {}", format_time(last));
+
+            if first > current_time {
+                println!("    warning: no present event");
+                warning_count += 1;
+            }
+
+            for &(start, stop) in &coverage.gap_list {
+                println!("    warning: gap {} .. {} ({} min)",
+                    format_time(start), format_time(stop), (stop - start) / 60);
+                warning_count += 1;
+            }
+
+            for &(start, stop) in &coverage.overlap_list {
+                println!("    warning: overlap {} .. {} ({} min)",
+                    format_time(start), format_time(stop), (stop - start) / 60);
+                warning_count += 1;
+            }
+        }
+
+        println!("services: {}, errors: {}, warnings: {}",
+            self.service_list.len() + self.skipped_list.len(), error_count, warning_count);
+
+        error_count == 0
+    }
+
+    /// Prints EIT sections of all streams as they would be sent now
+    fn dump(&mut self, format: DumpFormat) {
+        let current_time = chrono::Utc::now().timestamp() as u64;
+        let mut dst = String::new();
+
+        if format == DumpFormat::Json {
+            dst.push('[');
+        }
+
+        for (i, stream) in self.stream_list.iter().enumerate() {
+            let mut section_list = Vec::new();
+
+            for &(service_id, other) in &stream.eit_list {
+                for &table in &[
+                    Table::Present(service_id, other),
+                    Table::Schedule(service_id, other),
+                    Table::ScheduleLater(service_id, other),
+                ] {
+                    let psi_list = assemble_table(table,
+                        &mut self.service_list,
+                        &self.multiplex_list,
+                        None,
+                        current_time);
+                    for psi in &psi_list {
+                        let data = &psi.buffer[.. section::size(psi)];
+                        section_list.extend(dump::Section::parse(data));
+                    }
+                }
+            }
+
+            match format {
+                DumpFormat::Text => dump::write_text(&mut dst, &stream.output_addr, &section_list),
+                DumpFormat::Json => {
+                    if i != 0 {
+                        dst.push(',');
+                    }
+                    dump::write_json(&mut dst, &stream.output_addr, &section_list);
+                }
+            }
+        }
+
+        if format == DumpFormat::Json {
+            dst.push_str("]\n");
+        }
+
+        print!("{}", dst);
+    }
+
+    /// Updates snapshot for the status listener
+    fn update_status(&self, status: &mut Status, now: time::Instant) {
+        status.update_time = chrono::Utc::now().timestamp() as u64;
+
+        status.xmltv_list = self.epg_list.iter().map(|epg_item| XmltvStatus {
+            path: epg_item.path.clone(),
+            load_time: epg_item.update_time,
+            error: epg_item.error.clone(),
+        }).collect();
+
+        status.service_list = self.service_list.iter().map(|service| ServiceStatus {
+            name: service.name.clone(),
+            xmltv_id: service.xmltv_id.clone(),
+            onid: service.onid,
+            tsid: service.tsid,
+            pnr: service.pnr,
+            events_loaded: service.event_count,
+            events_scheduled: service.schedule.items.len(),
+            present: service.present.items.first()
+                .map(|item| service.title(item).to_owned())
+                .unwrap_or_default(),
+            following: service.following.as_ref()
+                .map(|item| service.title(item).to_owned())
+                .unwrap_or_default(),
+            present_version: service.present.version,
+            // version of the first sub-table
+            schedule_version: service.schedule_tables.version_list[0],
+            sections_sent: service.sections_sent,
+            bytes_sent: service.bytes_sent,
+        }).collect();
+
+        let stream_list = self.stream_list.iter().map(|stream| StreamStatus {
+            output: stream.output_addr.clone(),
+            rate_limit: stream.rate_limit * 8 / 1000,
+            bytes_sent: stream.output_meter.total(),
+            table_bytes_sent: stream.table_meter.total(),
+            is_overload: stream.is_overload,
+            ..Default::default()
+        }).collect();
+        status.set_stream_list(stream_list, now);
+    }
+
+    /// Builds SDT and EIT schedule for all services
+    fn load(&mut self) {
+        self.load_sdt();
+
+        for epg_item_id in 0 .. self.epg_list.len() {
+            self.load_schedule(epg_item_id);
+        }
+    }
+
+
+    fn open_xmltv(&mut self, config: &Config, def: usize) -> Result<Option<usize>> {
+        let path = match config.get("xmltv") {
+            Some(v) => v,
+            None => return Ok(Some(def)),
+        };
+
+        if let Some(&v) = self.epg_map.get(path) {
+            return Ok(Some(v));
+        }
+
+        let mut epg_item = EpgItem {
+            path: path.to_owned(),
+            ..Default::default()
+        };
+        if xmltv::is_remote(path) {
+            epg_item.remote = Some(Remote::new(path, &self.xmltv_cache));
+        }
+
+        // on reload source is not loaded in the output loop.
+        // see Instance::reload
+        let is_deferred = match &self.xmltv_prev {
+            Some(v) => v.contains_key(path) || epg_item.remote.is_some(),
+            None => false,
+        };
+
+        if ! is_deferred {
+            let result = epg_item.load();
+            epg_item.set_status(&result);
+            if let Err(e) = result {
+                error!(ctx: path, "failed to load XMLTV [{}]", e);
+                return Ok(None);
+            }
+        }
+
+        let v = self.epg_list.len();
+        self.epg_list.push(epg_item);
+        self.epg_map.insert(path.to_owned(), v);
+
+        Ok(Some(v))
+    }
+
+    /// Builds EIT schedule for all services linked with the XMLTV source
+    fn load_schedule(&mut self, epg_item_id: usize) {
+        let epg = &self.epg_list[epg_item_id].epg;
+
+        for service in &mut self.service_list {
+            if service.epg_item_id == epg_item_id {
+                service.load_schedule(epg, &self.country, self.eit_days, &self.event_options);
+            }
+        }
+    }
+
+    /// Reloads modified or expired XMLTV sources and rebuilds schedule
+    /// Remote sources are downloaded in background
+    /// and applied on next check when download is completed
+    fn reload_xmltv(&mut self) {
+        for epg_item_id in 0 .. self.epg_list.len() {
+            let epg_item = &mut self.epg_list[epg_item_id];
+
+            if epg_item.remote.is_some() {
+                let result = epg_item.remote.as_mut().and_then(Remote::poll);
+                if let Some(result) = result {
+                    let result = epg_item.apply_fetch(result);
+                    epg_item.set_status(&result);
+                    match result {
+                        Ok(true) => self.load_schedule(epg_item_id),
+                        Ok(false) => {},
+                        Err(e) => error!(ctx: &epg_item.path, "failed to reload XMLTV [{}]", e),
+                    };
+                } else if epg_item.is_expired(self.xmltv_reload) {
+                    if let Some(remote) = &mut epg_item.remote {
+                        remote.spawn();
+                    }
+                }
+                continue;
+            }
+
+            if ! epg_item.is_expired(self.xmltv_reload) {
+                continue;
+            }
+
+            let result = epg_item.load();
+            epg_item.set_status(&result);
+            if let Err(e) = result {
+                error!(ctx: &epg_item.path, "failed to reload XMLTV [{}]", e);
+                // try again on next interval
+                epg_item.load_time = Some(time::Instant::now());
+                continue;
+            }
+
+            self.load_schedule(epg_item_id);
+        }
+    }
+
+    /// Opens outputs and inputs for all streams
+    fn open_stream(&mut self) -> Result<()> {
+        let mut pool = Vec::new();
+        let mut input_pool = Vec::new();
+        for stream in &mut self.stream_list {
+            stream.output = OutputList::open(&stream.output_addr, &mut pool)?;
+            stream.open_input(&mut input_pool)?;
+        }
+        Ok(())
+    }
+
+    fn parse_config(&mut self, config: &Config) -> Result<()> {
+        if ! config.get("enable").unwrap_or(true) {
+            return Ok(())
+        }
+
+        let multiplex_id = self.multiplex_list.len();
+
+        let mut multiplex = Multiplex {
+            onid: config.get("onid")
+                .unwrap_or(self.onid),
+            codepage: config.get("codepage")
+                .unwrap_or(self.codepage),
+            utc_offset: config.get("utc-offset")
+                .map(parse_offset)
+                .unwrap_or(self.utc_offset),
+            tsid: config.get("tsid")
+                .unwrap_or(1),
+            eit_other: config.get("eit-other")
+                .unwrap_or(false),
+            sdt_enable: config.get("sdt")
+                .unwrap_or(false),
+            sdt_other: config.get("sdt-other")
+                .unwrap_or(false),
+            eit_rate: config.get("eit-rate"),
+            cbr: config.get("cbr"),
+            discover: config.get("discover")
+                .unwrap_or(false),
+
+            ..Default::default()
+        };
+
+        if let Some(v) = config.get("output") {
+            multiplex.output_addr.push_str(v);
+        }
+
+        if let Some(v) = config.get("input") {
+            multiplex.input_addr.push_str(v);
+        }
+
+        match self.open_xmltv(config, self.epg_item_id)? {
+            Some(v) => multiplex.epg_item_id = v,
+            None => {
+                for s in config.iter().filter(|s| s.get_name() == "service") {
+                    self.skipped_list.push((config_context(s), "xmltv not loaded"));
+                }
+                return Ok(());
+            }
+        };
+
+        for s in config.iter() {
+            if s.get_name() != "service" {
+                continue;
+            }
+
+            let mut service = Service {
+                multiplex_id,
+                onid: multiplex.onid,
+                tsid: multiplex.tsid,
                 codepage: s.get("codepage")
-                    .unwrap_or(self.multiplex.codepage),
+                    .unwrap_or(multiplex.codepage),
                 utc_offset: s.get("utc-offset")
                     .map(parse_offset)
-                    .unwrap_or(self.multiplex.utc_offset),
+                    .unwrap_or(multiplex.utc_offset),
                 parental_rating: s.get("parental-rating")
                     .unwrap_or(0),
                 pnr: s.get("pnr")
                     .unwrap_or(0),
+                service_type: s.get("service-type")
+                    .unwrap_or(1),
+                filler: self.filler.clone(),
 
                 ..Default::default()
             };
 
+            if service.pnr != 0 {
+                service.fixed = true;
+            } else if multiplex.input_addr.is_empty() {
+                warning!("'pnr' option not defined for service at line {}", s.get_line());
+                self.skipped_list.push((config_context(s), "pnr not defined"));
+                continue;
+            }
+
+            if let Some(v) = s.get("name") {
+                service.name.push_str(v);
+            }
+            if let Some(v) = s.get("provider") {
+                service.provider.push_str(v);
+            }
+
             let xmltv_id = match s.get("xmltv-id") {
                 Some(v) => {
                     service.xmltv_id.push_str(v);
                     v
                 }
                 None => {
-                    eprintln!("Warning: 'xmltv-id' option not defined for service at line {}", s.get_line());
+                    warning!("'xmltv-id' option not defined for service at line {}", s.get_line());
+                    self.skipped_list.push((config_context(s), "xmltv-id not defined"));
+                    continue;
+                }
+            };
+
+            match self.open_xmltv(s, multiplex.epg_item_id)? {
+                Some(v) => service.epg_item_id = v,
+                None => {
+                    self.skipped_list.push((config_context(s), "xmltv not loaded"));
+                    continue;
+                }
+            };
+
+            if service.epg_item_id == usize::max_value() {
+                error!(ctx: xmltv_id, "XMLTV for the service is not found");
+                self.skipped_list.push((config_context(s), "xmltv not defined"));
+                continue;
+            }
+
+            self.service_list.push(service);
+        }
+
+        self.multiplex_list.push(multiplex);
+
+        Ok(())
+    }
+
+    fn parse_tdt_tot(&mut self, config: &Config) -> Result<()> {
+        if let Some(t) = &mut self.tdt_tot {
+            t.parse_config(config)?;
+        } else {
+            let mut t = TdtTot::default();
+            t.parse_config(config)?;
+            self.tdt_tot = Some(t);
+        }
+
+        Ok(())
+    }
+
+    /// Groups multiplexes into streams by output address.
+    /// Multiplex without own output goes to the common output.
+    /// Service of other multiplex is announced in the EIT other tables
+    /// if any multiplex in the stream has `eit-other` option.
+    /// Same for SDT other and `sdt-other` option
+    fn link_streams(&mut self) -> Result<()> {
+        let mut stream_list = Vec::new();
+
+        if ! self.output_addr.is_empty() {
+            stream_list.push(Stream::new(&self.output_addr));
+        }
+
+        for (multiplex_id, multiplex) in self.multiplex_list.iter().enumerate() {
+            let addr = if ! multiplex.output_addr.is_empty() {
+                &multiplex.output_addr
+            } else if ! self.output_addr.is_empty() {
+                &self.output_addr
+            } else {
+                return Err(AppError::MissingOutput);
+            };
+
+            let pos = match stream_list.iter().position(|s: &Stream| s.output_addr == *addr) {
+                Some(v) => v,
+                None => {
+                    stream_list.push(Stream::new(addr));
+                    stream_list.len() - 1
+                }
+            };
+
+            let stream = &mut stream_list[pos];
+            stream.multiplex_list.push(multiplex_id);
+
+            if ! multiplex.input_addr.is_empty() {
+                if stream.input_addr.is_empty() {
+                    stream.input_addr.push_str(&multiplex.input_addr);
+                } else if stream.input_addr != multiplex.input_addr {
+                    return Err(AppError::InputConflict(addr.clone()));
+                }
+            }
+
+            if stream.eit_rate.is_none() {
+                stream.eit_rate = multiplex.eit_rate;
+            }
+            if stream.cbr.is_none() {
+                stream.cbr = multiplex.cbr;
+            }
+        }
+
+        // checked before reload changes running streams
+        let is_missing = stream_list.iter()
+            .any(|s| parse_addr_list(&s.output_addr).is_empty());
+        if stream_list.is_empty() || is_missing {
+            return Err(AppError::MissingOutput);
+        }
+
+        self.stream_list = stream_list;
+        self.link_tables();
+
+        Ok(())
+    }
+
+    /// Defines services and multiplexes announced in each stream.
+    /// Services with unknown program number are skipped
+    fn link_tables(&mut self) {
+        let multiplex_list = &self.multiplex_list;
+
+        for stream in &mut self.stream_list {
+            // multiplexes of the stream are announced in the actual tables only
+            let is_other = |multiplex_id: usize, check: fn(&Multiplex) -> bool| {
+                ! stream.multiplex_list.contains(&multiplex_id) &&
+                stream.multiplex_list.iter().any(|&id| check(&multiplex_list[id]))
+            };
+
+            let mut eit_list = Vec::new();
+            for (service_id, service) in self.service_list.iter().enumerate() {
+                if service.pnr != 0 && stream.multiplex_list.contains(&service.multiplex_id) {
+                    eit_list.push((service_id, false));
+                }
+            }
+            for (service_id, service) in self.service_list.iter().enumerate() {
+                if service.pnr != 0 && is_other(service.multiplex_id, |m| m.eit_other) {
+                    eit_list.push((service_id, true));
+                }
+            }
+
+            let mut sdt_list = Vec::new();
+            for (multiplex_id, multiplex) in multiplex_list.iter().enumerate() {
+                if multiplex.sdt_enable && stream.multiplex_list.contains(&multiplex_id) {
+                    sdt_list.push((multiplex_id, false));
+                }
+            }
+            for multiplex_id in 0 .. multiplex_list.len() {
+                if is_other(multiplex_id, |m| m.sdt_other) {
+                    sdt_list.push((multiplex_id, true));
+                }
+            }
+
+            let mut table_list = Vec::new();
+            if self.tdt_tot.is_some() {
+                table_list.push(Table::TdtTot);
+            }
+            for &(multiplex_id, other) in &sdt_list {
+                table_list.push(Table::Sdt(multiplex_id, other));
+            }
+            for &(service_id, other) in &eit_list {
+                table_list.push(Table::Present(service_id, other));
+                table_list.push(Table::Schedule(service_id, other));
+                table_list.push(Table::ScheduleLater(service_id, other));
+            }
+
+            stream.scheduler = Scheduler::new(&table_list, &self.intervals);
+            stream.eit_list = eit_list;
+            stream.sdt_list = sdt_list;
+
+            let rate_limit = stream.eit_rate
+                .or(self.eit_rate)
+                .unwrap_or_else(|| cmp::max(stream.eit_list.len(), 1) * 30);
+            stream.rate_limit = rate_limit * 1000 / 8;
+            stream.clock.set_rate(stream.rate_limit as u64, time::Instant::now());
+            stream.cbr = stream.cbr.or(self.cbr);
+        }
+    }
+
+    /// Applies transport stream parameters found in the input streams.
+    /// Returns true if any multiplex has been changed
+    fn apply_discovery(&mut self) -> bool {
+        let mut update_list: Vec<(usize, TransportStream)> = Vec::new();
+
+        for stream in &mut self.stream_list {
+            let ts = match stream.discovery.take_update() {
+                Some(v) => v,
+                None => continue,
+            };
+
+            for &multiplex_id in &stream.multiplex_list {
+                if self.multiplex_list[multiplex_id].input_addr == stream.input_addr {
+                    update_list.push((multiplex_id, ts.clone()));
+                }
+            }
+        }
+
+        if update_list.is_empty() {
+            return false;
+        }
+
+        for (multiplex_id, ts) in &update_list {
+            self.discover(*multiplex_id, ts);
+        }
+
+        self.link_tables();
+        true
+    }
+
+    /// Updates multiplex and services with parameters found in the input stream.
+    /// Services without program number are matched by service name or
+    /// by name of the XMLTV channel.
+    /// If multiplex has `discover` option then other services with
+    /// known XMLTV channel name are added automatically
+    fn discover(&mut self, multiplex_id: usize, ts: &TransportStream) {
+        let multiplex = &mut self.multiplex_list[multiplex_id];
+        multiplex.tsid = ts.tsid;
+        if let Some(onid) = ts.onid {
+            multiplex.onid = onid;
+        }
+        let multiplex = &self.multiplex_list[multiplex_id];
+
+        // service could have own XMLTV source
+        let epg_list = &self.epg_list;
+        let channel_name = |service: &Service| {
+            epg_list.get(service.epg_item_id)
+                .and_then(|v| v.epg.channels.get(&service.xmltv_id))
+                .map(|v| v.name.as_str())
+        };
+
+        // added services will be created again. keeps tables to save versions
+        let mut prev_list = Vec::new();
+        let mut i = 0;
+        while i < self.service_list.len() {
+            let service = &self.service_list[i];
+            if service.multiplex_id == multiplex_id && service.auto {
+                prev_list.push(self.service_list.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut pnr_list = Vec::new();
+
+        for service in &mut self.service_list {
+            if service.multiplex_id != multiplex_id {
+                continue;
+            }
+
+            service.onid = multiplex.onid;
+            service.tsid = multiplex.tsid;
+
+            if service.fixed {
+                pnr_list.push(service.pnr);
+                continue;
+            }
+
+            let item = ts.service_list.iter().find(|item| {
+                is_name_equal(&service.name, &item.name) ||
+                channel_name(service).is_some_and(|v| is_name_equal(v, &item.name))
+            });
+
+            match item {
+                Some(item) => {
+                    if service.pnr != item.pnr {
+                        info!(ctx: &service.xmltv_id, "found in the input stream with pnr:{}", item.pnr);
+                        if service.pnr == 0 {
+                            service.pnr = item.pnr;
+                            service.restore(&self.state);
+                        }
+                    }
+                    service.pnr = item.pnr;
+                    pnr_list.push(item.pnr);
+                }
+                None => {
+                    if service.pnr != 0 {
+                        warning!(ctx: &service.context(), "not found in the input stream");
+                    }
+                    service.pnr = 0;
+                }
+            }
+        }
+
+        let epg = match self.epg_list.get(multiplex.epg_item_id) {
+            Some(v) if multiplex.discover => &v.epg,
+            _ => return,
+        };
+
+        for item in &ts.service_list {
+            if item.name.is_empty() || pnr_list.contains(&item.pnr) {
+                continue;
+            }
+
+            let xmltv_id = epg.channels.iter()
+                .filter(|(_, v)| is_name_equal(&v.name, &item.name))
+                .map(|(k, _)| k)
+                .min();
+
+            let mut service = Service {
+                epg_item_id: multiplex.epg_item_id,
+                multiplex_id,
+                onid: multiplex.onid,
+                tsid: multiplex.tsid,
+                codepage: multiplex.codepage,
+                utc_offset: multiplex.utc_offset,
+                pnr: item.pnr,
+                name: item.name.clone(),
+                provider: item.provider.clone(),
+                service_type: item.service_type,
+                filler: self.filler.clone(),
+                auto: true,
+
+                ..Default::default()
+            };
+
+            match xmltv_id {
+                Some(v) => service.xmltv_id.push_str(v),
+                None => continue,
+            };
+
+            let prev = prev_list.iter_mut().find(|s| {
+                s.pnr == service.pnr && s.xmltv_id == service.xmltv_id
+            });
+
+            if let Some(prev) = prev {
+                service.present = std::mem::take(&mut prev.present);
+                service.following = prev.following.take();
+                service.schedule = std::mem::take(&mut prev.schedule);
+                service.schedule_tables = std::mem::take(&mut prev.schedule_tables);
+                service.title_map = std::mem::take(&mut prev.title_map);
+                service.last_stop = prev.last_stop;
+                service.sections_sent = prev.sections_sent;
+                service.bytes_sent = prev.bytes_sent;
+            } else {
+                info!(ctx: &service.context(), "added from the input stream");
+                service.restore(&self.state);
+            }
+
+            self.service_list.push(service);
+        }
+    }
+
+    /// Builds SDT for each multiplex.
+    /// Table version is changed if service list has been changed
+    fn load_sdt(&mut self) {
+        for (multiplex_id, multiplex) in self.multiplex_list.iter_mut().enumerate() {
+            let mut sdt = Sdt {
+                table_id: 0x42,
+                version: multiplex.sdt.version,
+                tsid: multiplex.tsid,
+                onid: multiplex.onid,
+                ..Default::default()
+            };
+
+            for service in &self.service_list {
+                if service.multiplex_id != multiplex_id || service.pnr == 0 {
                     continue;
                 }
+
+                let mut item = SdtItem {
+                    pnr: service.pnr,
+                    schedule_flag: 1,
+                    present_flag: 1,
+                    running_status: 4,
+                    ..Default::default()
+                };
+
+                let name = if service.name.is_empty() {
+                    &service.xmltv_id
+                } else {
+                    &service.name
+                };
+
+                item.descriptors.push(Desc48 {
+                    type_: service.service_type,
+                    provider: textcode::StringDVB::from_str(&service.provider, service.codepage),
+                    name: textcode::StringDVB::from_str(name, service.codepage),
+                });
+
+                sdt.items.push(item);
+            }
+
+            if ! is_table_equal(&multiplex.sdt, &sdt) && ! multiplex.sdt.items.is_empty() {
+                sdt.version = (sdt.version + 1) % 32;
+            }
+
+            multiplex.sdt = sdt;
+        }
+    }
+}
+
+
+#[derive(Default, Debug)]
+struct Multiplex {
+    epg_item_id: usize,
+
+    onid: u16,
+    tsid: u16,
+    codepage: u8,
+    utc_offset: i32,
+
+    eit_other: bool,
+    sdt_enable: bool,
+    sdt_other: bool,
+
+    /// Own output. Empty for common output
+    output_addr: String,
+    /// Input for remux mode. Empty for standalone stream
+    input_addr: String,
+    /// Add services found in the input stream
+    discover: bool,
+    eit_rate: Option<usize>,
+    cbr: Option<bool>,
+
+    sdt: Sdt,
+}
+
+
+impl Multiplex {
+    /// Assembles SDT sections.
+    /// `table_id` is 0x42 for actual or 0x46 for other transport stream
+    fn sdt_assemble(&self, table_id: u8) -> Vec<Psi> {
+        let mut psi_list = self.sdt.psi_list_assemble();
+        if table_id != self.sdt.table_id {
+            for p in &mut psi_list {
+                section::set_table_id(p, table_id);
+                section::finalize(p);
+            }
+        }
+        psi_list
+    }
+}
+
+
+#[derive(Default, Debug)]
+struct Service {
+    epg_item_id: usize,
+    multiplex_id: usize,
+
+    onid: u16,
+    tsid: u16,
+    codepage: u8,
+    utc_offset: i32,
+    parental_rating: u8,
+
+    pnr: u16,
+    /// Program number defined in config. Otherwise found in the input stream
+    fixed: bool,
+    /// Service added from the input stream
+    auto: bool,
+    xmltv_id: String,
+
+    name: String,
+    provider: String,
+    service_type: u8,
+
+    /// Present event. Empty if there is a gap between events
+    present: Eit,
+    following: Option<EitItem>,
+    schedule: Eit,
+    /// Assembled schedule sections and sub-table versions
+    schedule_tables: ScheduleTables,
+    /// Stop time of the last finished event or time when gap has been found.
+    /// Start of the filler event
+    last_stop: u64,
+    /// Title of the filler event for gaps. Empty if disabled
+    filler: String,
+
+    /// Events of the service in XMLTV
+    event_count: usize,
+    /// Titles of the scheduled events by event identifier
+    title_map: HashMap<u16, String>,
+    sections_sent: u64,
+    bytes_sent: u64,
+
+    ts: Vec<u8>,
+}
+
+
+impl Service {
+    /// Sets table versions next to the versions saved on previous run.
+    /// Receivers could keep tables with saved versions in the cache
+    fn restore(&mut self, state: &State) {
+        if let Some(v) = state.service_map.get(&(self.onid, self.tsid, self.pnr)) {
+            self.present.version = (v.present_version + 1) % 32;
+            for (version, saved) in self.schedule_tables.version_list.iter_mut().zip(&v.schedule_version) {
+                *version = (saved + 1) % 32;
+            }
+        }
+    }
+
+    /// Builds EIT schedule from XMLTV.
+    /// Table versions are changed if events have been changed
+    fn load_schedule(&mut self,
+        epg: &Epg,
+        country: &str,
+        eit_days: usize,
+        event_options: &EventOptions)
+    {
+        // Present+Following. Sent without events if channel not found
+        self.present.table_id = 0x4E;
+        self.present.pnr = self.pnr;
+        self.present.tsid = self.tsid;
+        self.present.onid = self.onid;
+
+        let epg_item = match epg.channels.get(&self.xmltv_id) {
+            Some(v) => v,
+            None => {
+                warning!(ctx: &self.context(), "not found in XMLTV");
+                self.event_count = 0;
+                return;
+            },
+        };
+        self.event_count = epg_item.events.len();
+
+        let now = chrono::Utc::now();
+        let current_time = now.timestamp() as u64;
+        let last_time = (now + chrono::Duration::days(eit_days as i64)).timestamp() as u64;
+
+        let country_code = rating::country_code(country);
+
+        // Schedule
+        let mut schedule = Eit {
+            table_id: 0x50,
+            pnr: self.pnr,
+            tsid: self.tsid,
+            onid: self.onid,
+            ..Default::default()
+        };
+
+        let mut event_id_list = HashSet::new();
+        let mut title_map = HashMap::new();
+        let mut unknown_rating = HashSet::new();
+
+        for event in &epg_item.events {
+            let start = ((event.start as i64) - (self.utc_offset as i64) * 60) as u64;
+            let stop = ((event.stop as i64) - (self.utc_offset as i64) * 60) as u64;
+
+            if start > last_time {
+                break;
+            }
+
+            if stop > current_time {
+                // XMLTV event is shared between services and kept on reload
+                let mut parental_rating = event.parental_rating.clone();
+                for (system, value) in &event.rating {
+                    if ! rating::is_known(system) {
+                        unknown_rating.insert(system.as_str());
+                        continue;
+                    }
+
+                    let (code, age) = match rating::parse(system, value) {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    if let Some(code) = code.or(country_code) {
+                        parental_rating.insert(code, age);
+                    }
+                }
+
+                // service option is used if XMLTV has no rating for the country
+                if self.parental_rating != 0 {
+                    if let Some(code) = country_code {
+                        parental_rating
+                            .entry(code)
+                            .or_insert(self.parental_rating);
+                    }
+                }
+
+                let mut item = event::eit_item(event, self.codepage, &parental_rating, event_options);
+                item.start = start;
+
+                let event_id = event::unique_event_id(start, &event.title, |v| event_id_list.insert(v));
+                item.event_id = event_id;
+                title_map.insert(event_id, event.title.clone());
+
+                schedule.items.push(item);
+            }
+        }
+
+        for system in unknown_rating {
+            warning!(ctx: &self.context(), "unknown rating system '{}' skipped", system);
+        }
+
+        if schedule.items.is_empty() {
+            warning!(ctx: &self.context(), "has empty list");
+        } else {
+            debug!(ctx: &self.context(), "{} events scheduled", schedule.items.len());
+        }
+
+        // present/following updates from new schedule on next assembling.
+        // sub-table versions changed on assembling if sections have been changed
+        self.schedule = schedule;
+        self.schedule_tables.invalidate();
+        self.title_map = title_map;
+    }
+
+    /// Returns service identifier for log messages
+    fn context(&self) -> String {
+        if self.pnr != 0 {
+            format!("{}:{}", &self.xmltv_id, self.pnr)
+        } else {
+            self.xmltv_id.clone()
+        }
+    }
+
+    /// Returns title of the event or filler title if event not found in schedule
+    fn title(&self, item: &EitItem) -> &str {
+        self.title_map.get(&item.event_id).map(String::as_str).unwrap_or(&self.filler)
+    }
+
+    /// Returns EIT present/following table with same header as present table
+    fn pf_table(&self, item: Option<EitItem>) -> Eit {
+        Eit {
+            table_id: self.present.table_id,
+            version: self.present.version,
+            pnr: self.present.pnr,
+            tsid: self.present.tsid,
+            onid: self.present.onid,
+            items: item.into_iter().collect(),
+        }
+    }
+
+    /// Removes finished events from schedule and selects present and following events.
+    /// Present is empty if there is a gap between events, or filler event if defined.
+    /// Following is always the next event after present time.
+    /// Table version is changed if events or their status have been changed
+    fn update_present(&mut self, current_time: u64) {
+        let mut is_removed = false;
+        while let Some(item) = self.schedule.items.first() {
+            let stop = item.start + u64::from(item.duration);
+            if stop > current_time {
+                break;
+            }
+            self.last_stop = stop;
+            self.schedule.items.remove(0);
+            is_removed = true;
+        }
+
+        if is_removed {
+            self.schedule_tables.invalidate();
+        }
+
+        let mut present = None;
+        let mut following = None;
+
+        let mut items = self.schedule.items.iter();
+        if let Some(item) = items.next() {
+            if item.start <= current_time {
+                present = Some(item.clone());
+                following = items.next().cloned();
+            } else {
+                following = Some(item.clone());
+            }
+        }
+
+        // running
+        if let Some(item) = &mut present {
+            item.status = 4;
+        }
+
+        // starts in a few seconds or not running
+        if let Some(item) = &mut following {
+            item.status = if item.start <= current_time + STARTING_INTERVAL { 2 } else { 1 };
+        }
+
+        if present.is_none() && ! self.filler.is_empty() {
+            if let Some(next) = &following {
+                // gap start pinned to keep same filler event until next event
+                if self.last_stop == 0 || self.last_stop > current_time {
+                    self.last_stop = current_time - current_time % 60;
+                }
+
+                // identifier not used by any event of the service
+                let mut item = event::filler_item(self.last_stop, next.start, &self.filler, self.codepage);
+                let title_map = &self.title_map;
+                item.event_id = event::unique_event_id(self.last_stop, &self.filler, |v| {
+                    ! title_map.contains_key(&v)
+                });
+                item.status = 4;
+                present = Some(item);
+            }
+        }
+
+        let present = self.pf_table(present);
+        let is_changed =
+            ! is_table_equal(&self.present, &present) ||
+            ! is_table_equal(&self.pf_table(self.following.clone()), &self.pf_table(following.clone()));
+
+        if is_changed {
+            self.present.items = present.items;
+            self.present.version = (self.present.version + 1) % 32;
+            self.following = following;
+        }
+    }
+
+    /// Assembles EIT present/following sections.
+    /// `table_id` is 0x4E for actual or 0x4F for other transport stream.
+    /// Section 0 contains present event, section 1 - following event.
+    /// Section without event is used if event is not defined
+    fn present_assemble(&self, table_id: u8) -> Vec<Psi> {
+        let mut psi_list = Vec::new();
+
+        let table_list = [
+            self.pf_table(self.present.items.first().cloned()),
+            self.pf_table(self.following.clone()),
+        ];
+
+        for (section_number, eit) in table_list.iter().enumerate() {
+            let mut section_list = eit.psi_list_assemble();
+            section_list.truncate(1);
+
+            for p in &mut section_list {
+                section::set_table_id(p, table_id);
+                section::eit_set_number(p, section_number as u8, 1, 1, table_id);
+                section::finalize(p);
+            }
+
+            psi_list.append(&mut section_list);
+        }
+
+        psi_list
+    }
+
+    /// Returns EIT schedule sections accepted with `filter`.
+    /// `table_id` is 0x50 for actual or 0x60 for other transport stream.
+    /// Sections are assembled again only if schedule or current day have been changed
+    fn schedule_assemble<F>(&mut self, table_id: u8, current_time: u64, filter: F) -> Vec<Psi>
+    where
+        F: Fn(&Psi) -> bool,
+    {
+        let dropped_events = self.schedule_tables.dropped_events;
+
+        // report once when number of dropped events changes
+        if self.schedule_tables.update(&self.schedule, current_time) &&
+            self.schedule_tables.dropped_events != dropped_events &&
+            self.schedule_tables.dropped_events != 0
+        {
+            warning!(ctx: &self.context(),
+                "{} events dropped. 3-hour segment exceeds {} sections",
+                self.schedule_tables.dropped_events, SECTIONS_PER_SEGMENT);
+        }
+
+        self.schedule_tables.sections(table_id, filter)
+    }
+}
+
+
+/// Output stream with tables of one or more multiplexes
+#[derive(Debug)]
+struct Stream {
+    output_addr: String,
+    output: OutputList,
+
+    input_addr: String,
+    input: Option<Input>,
+    input_failed: bool,
+    /// Not processed data from input
+    input_buffer: Vec<u8>,
+    /// Remuxed data. Sent by blocks
+    remux_buffer: Vec<u8>,
+    discovery: Discovery,
+    /// SDT PID of the input if stream has own SDT actual
+    sdt_reader: SectionReader,
+    /// Sections from SDT PID of the input not replaced with own tables. BAT for example
+    sdt_passthrough: Vec<Vec<u8>>,
+
+    multiplex_list: Vec<usize>,
+    /// Services announced in the EIT: service id and true for other tables
+    eit_list: Vec<(usize, bool)>,
+    /// Multiplexes announced in the SDT: multiplex id and true for other table
+    sdt_list: Vec<(usize, bool)>,
+
+    scheduler: Scheduler,
+    is_overload: bool,
+
+    eit_rate: Option<usize>,
+    /// Output rate in bytes per second
+    rate_limit: usize,
+    /// Stuff output with null packets up to the rate limit
+    cbr: Option<bool>,
+    clock: Clock,
+    /// Measured rate of the output
+    output_meter: RateMeter,
+    /// Measured rate of the tables in the output
+    table_meter: RateMeter,
+
+    eit_cc: u8,
+    sdt_cc: u8,
+    tdt_cc: u8,
+
+    ts_buffer: Vec<u8>,
+    ts_skip: usize,
+    next_send: time::Instant,
+}
+
+
+impl Stream {
+    fn new(output_addr: &str) -> Self {
+        Stream {
+            output_addr: output_addr.to_owned(),
+            output: OutputList::default(),
+
+            input_addr: String::new(),
+            input: None,
+            input_failed: false,
+            input_buffer: Vec::new(),
+            remux_buffer: Vec::new(),
+            discovery: Discovery::default(),
+            sdt_reader: SectionReader::default(),
+            sdt_passthrough: Vec::new(),
+
+            multiplex_list: Vec::new(),
+            eit_list: Vec::new(),
+            sdt_list: Vec::new(),
+
+            scheduler: Scheduler::default(),
+            is_overload: false,
+
+            eit_rate: None,
+            rate_limit: 0,
+            cbr: None,
+            clock: Clock::default(),
+            output_meter: RateMeter::default(),
+            table_meter: RateMeter::default(),
+
+            eit_cc: 0,
+            sdt_cc: 0,
+            tdt_cc: 0,
+
+            ts_buffer: Vec::new(),
+            ts_skip: 0,
+            next_send: time::Instant::now(),
+        }
+    }
+
+    /// Takes continuity counters and not sent data from previous stream
+    fn keep(&mut self, prev: &mut Stream) {
+        self.eit_cc = prev.eit_cc;
+        self.sdt_cc = prev.sdt_cc;
+        self.tdt_cc = prev.tdt_cc;
+        self.ts_buffer = std::mem::take(&mut prev.ts_buffer);
+        self.ts_skip = prev.ts_skip;
+        self.next_send = prev.next_send;
+        self.output_meter = std::mem::take(&mut prev.output_meter);
+        self.table_meter = std::mem::take(&mut prev.table_meter);
+
+        let now = time::Instant::now();
+        self.clock = std::mem::take(&mut prev.clock);
+        self.clock.set_rate(self.rate_limit as u64, now);
+
+        if self.input_addr == prev.input_addr {
+            self.input_buffer = std::mem::take(&mut prev.input_buffer);
+            self.remux_buffer = std::mem::take(&mut prev.remux_buffer);
+            self.discovery = std::mem::take(&mut prev.discovery);
+            self.discovery.refresh();
+            self.sdt_reader = std::mem::take(&mut prev.sdt_reader);
+            self.sdt_passthrough = std::mem::take(&mut prev.sdt_passthrough);
+        }
+    }
+
+    /// Opens input for remux mode if defined.
+    /// Already opened input with same address is taken from `pool`
+    fn open_input(&mut self, pool: &mut Vec<(String, Input)>) -> Result<()> {
+        if self.input_addr.is_empty() {
+            return Ok(());
+        }
+
+        let input = match pool.iter().position(|(addr, _)| *addr == self.input_addr) {
+            Some(pos) => pool.remove(pos).1,
+            None => Input::open(&self.input_addr)?,
+        };
+        self.input = Some(input);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.ts_skip >= self.ts_buffer.len()
+    }
+
+    /// Fills buffer with next sections selected by scheduler.
+    /// Buffer contains at least one block if there are tables to send.
+    /// Buffer aligned to the block size with null packets
+    fn fill(&mut self,
+        service_list: &mut [Service],
+        multiplex_list: &[Multiplex],
+        mut tdt_tot: Option<&mut TdtTot>,
+        now: time::Instant,
+        current_time: u64)
+    {
+        self.ts_buffer.clear();
+        self.ts_skip = 0;
+
+        // sections from the input sent before own tables
+        for buffer in self.sdt_passthrough.drain(..) {
+            let mut psi = Psi {
+                buffer,
+                pid: psi::SDT_PID,
+                cc: self.sdt_cc,
+                ..Default::default()
             };
+            psi.demux(&mut self.ts_buffer);
+            self.sdt_cc = psi.cc;
+        }
 
-            match self.open_xmltv(s, self.multiplex.epg_item_id)? {
-                Some(v) => service.epg_item_id = v,
-                None => continue,
+        while self.ts_buffer.len() < BLOCK_SIZE {
+            let item = self.scheduler.next(now, |table| {
+                assemble_table(table, service_list, multiplex_list, tdt_tot.as_deref_mut(), current_time)
+            });
+
+            let (table, mut psi) = match item {
+                Some(v) => v,
+                None => break,
             };
 
-            if service.epg_item_id == usize::max_value() {
-                eprintln!("Error: XMLTV for channel {} is not found", xmltv_id);
-                continue;
+            let (pid, cc, service_id) = match table {
+                Table::TdtTot => (psi::TDT_PID, &mut self.tdt_cc, None),
+                Table::Sdt(..) => (psi::SDT_PID, &mut self.sdt_cc, None),
+                Table::Present(id, _) |
+                Table::Schedule(id, _) |
+                Table::ScheduleLater(id, _) => (psi::EIT_PID, &mut self.eit_cc, Some(id)),
+            };
+
+            let skip = self.ts_buffer.len();
+            psi.pid = pid;
+            psi.cc = *cc;
+            psi.demux(&mut self.ts_buffer);
+            *cc = psi.cc;
+
+            if let Some(service) = service_id.and_then(|id| service_list.get_mut(id)) {
+                service.sections_sent += 1;
+                service.bytes_sent += (self.ts_buffer.len() - skip) as u64;
             }
+        }
 
-            self.service_list.push(service);
+        self.table_meter.add(self.ts_buffer.len());
+        fill_null_ts(&mut self.ts_buffer);
+    }
+
+    /// Sends next block from buffer
+    fn send(&mut self) {
+        let next = cmp::min(self.ts_skip + BLOCK_SIZE, self.ts_buffer.len());
+        let block = &self.ts_buffer[self.ts_skip .. next];
+        self.output.send(block);
+        self.output_meter.add(block.len());
+        self.ts_skip = next;
+    }
+
+    /// Sends blocks in time defined by the output clock and defines time for next call.
+    /// In CBR mode output stuffed with null packets if there is nothing to send
+    fn process(&mut self,
+        service_list: &mut [Service],
+        multiplex_list: &[Multiplex],
+        mut tdt_tot: Option<&mut TdtTot>,
+        now: time::Instant,
+        current_time: u64)
+    {
+        let block_size = BLOCK_SIZE as u64;
+        let is_cbr = self.cbr.unwrap_or(false);
+        let mut is_idle = false;
+
+        self.clock.sync(now, CLOCK_MAX_LAG);
+
+        while self.clock.is_ready(now, block_size) {
+            if self.is_empty() {
+                self.fill(service_list, multiplex_list, tdt_tot.as_deref_mut(), now, current_time);
+                if self.is_empty() {
+                    if ! is_cbr {
+                        is_idle = true;
+                        break;
+                    }
+                    for _ in 0 .. BLOCK_SIZE / ts::PACKET_SIZE {
+                        self.ts_buffer.extend_from_slice(ts::NULL_PACKET);
+                    }
+                }
+            }
+
+            self.send();
+            self.clock.consume(block_size);
         }
 
-        Ok(())
+        self.next_send = if ! is_idle {
+            self.clock.deadline(block_size)
+        } else {
+            // nothing to send. wait for next table repetition
+            // without burst on wakeup
+            self.clock.sync(now, time::Duration::from_secs(0));
+            self.scheduler.next_deadline().unwrap_or(now + IDLE_DELAY)
+        };
+        self.next_send = cmp::max(self.next_send, now + INPUT_DELAY);
+
+        self.check_overload();
     }
 
-    fn parse_tdt_tot(&mut self, config: &Config) -> Result<()> {
-        if let Some(t) = &mut self.tdt_tot {
-            t.parse_config(config)?;
+    /// Reports if output rate is not enough to keep repetition intervals
+    fn check_overload(&mut self) {
+        let is_overload = self.scheduler.is_overload();
+        if is_overload == self.is_overload {
+            return;
+        }
+
+        self.is_overload = is_overload;
+        if is_overload {
+            warning!(ctx: &self.output_addr, "rate is too low to keep tables repetition intervals");
         } else {
-            let mut t = TdtTot::default();
-            t.parse_config(config)?;
-            self.tdt_tot = Some(t);
+            info!(ctx: &self.output_addr, "tables repetition intervals restored");
         }
+    }
 
-        Ok(())
+    /// Reports measured output rate once per `interval`
+    fn report(&mut self, now: time::Instant, interval: time::Duration) {
+        if self.output_meter.elapsed(now) < interval {
+            return;
+        }
+
+        let output_rate = self.output_meter.take(now);
+        let table_rate = self.table_meter.take(now);
+        info!(ctx: &self.output_addr, "rate {:.1} kbit/s, tables {:.1} kbit/s limit {} kbit/s",
+            output_rate,
+            table_rate,
+            self.rate_limit * 8 / 1000);
     }
-}
 
+    /// Appends next packet from buffer to `dst`. Fills buffer if empty.
+    /// Null packets used for block alignment are skipped.
+    /// Returns false if there is nothing to send
+    fn next_packet(&mut self,
+        service_list: &mut [Service],
+        multiplex_list: &[Multiplex],
+        mut tdt_tot: Option<&mut TdtTot>,
+        current_time: u64,
+        dst: &mut Vec<u8>) -> bool
+    {
+        let mut is_filled = false;
 
-#[derive(Default, Debug)]
-struct Multiplex {
-    epg_item_id: usize,
+        loop {
+            if self.is_empty() {
+                // prevents loop if buffer contains null packets only
+                if is_filled {
+                    return false;
+                }
+                is_filled = true;
 
-    onid: u16,
-    tsid: u16,
-    codepage: u8,
-    utc_offset: i32,
-}
+                let now = time::Instant::now();
+                self.fill(service_list, multiplex_list, tdt_tot.as_deref_mut(), now, current_time);
+                if self.is_empty() {
+                    return false;
+                }
+            }
 
+            let packet = &self.ts_buffer[self.ts_skip ..][.. ts::PACKET_SIZE];
+            self.ts_skip += ts::PACKET_SIZE;
 
-#[derive(Default, Debug)]
-struct Service {
-    epg_item_id: usize,
+            if ts_pid(packet) != NULL_PID {
+                dst.extend_from_slice(packet);
+                return true;
+            }
+        }
+    }
 
-    onid: u16,
-    tsid: u16,
-    codepage: u8,
-    utc_offset: i32,
-    parental_rating: u8,
+    /// Keeps SDT PID sections of the input not replaced with own tables:
+    /// SDT other for not announced multiplexes, BAT, and other tables
+    fn sdt_filter(&mut self, packet: &[u8], multiplex_list: &[Multiplex]) {
+        let mut section_list = Vec::new();
+        self.sdt_reader.push(packet, &mut section_list);
+
+        for data in section_list {
+            let tsid = u16::from_be_bytes([data[3], data[4]]);
+            let onid = u16::from_be_bytes([data[8], data[9]]);
+
+            let is_replaced = match data[0] {
+                0x42 => true,
+                0x46 => self.sdt_list.iter().any(|&(multiplex_id, other)| {
+                    let multiplex = &multiplex_list[multiplex_id];
+                    other && multiplex.tsid == tsid && multiplex.onid == onid
+                }),
+                _ => false,
+            };
 
-    pnr: u16,
-    xmltv_id: String,
+            if ! is_replaced {
+                self.sdt_passthrough.push(data);
+            }
+        }
+    }
 
-    present: Eit,
-    schedule: Eit,
+    /// Reads transport stream from input and puts generated tables
+    /// into null packets. Original EIT and TDT/TOT packets are dropped
+    /// and used as free slots too. Same for SDT PID if stream has own SDT actual,
+    /// sections of the input not replaced with own tables are sent with own SDT
+    fn remux(&mut self,
+        service_list: &mut [Service],
+        multiplex_list: &[Multiplex],
+        mut tdt_tot: Option<&mut TdtTot>,
+        current_time: u64)
+    {
+        let input = match self.input.as_mut() {
+            Some(v) => v,
+            None => return,
+        };
 
-    ts: Vec<u8>,
-}
+        let mut buffer = [0u8; 64 * 1024];
+        while self.input_buffer.len() < INPUT_READ_LIMIT {
+            match input.recv(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.input_buffer.extend_from_slice(&buffer[.. n]);
+                    if self.input_failed {
+                        self.input_failed = false;
+                        info!(ctx: &self.input_addr, "input restored");
+                    }
+                }
+                Err(e) => {
+                    if ! self.input_failed {
+                        self.input_failed = true;
+                        error!(ctx: &self.input_addr, "failed to read [{}]", e);
+                    }
+                    break;
+                }
+            }
+        }
 
+        let now = time::Instant::now();
+        let packet_size = ts::PACKET_SIZE as u64;
+        self.clock.sync(now, CLOCK_MAX_LAG);
 
-impl Service {
-    fn clear(&mut self) {
-        let current_time = chrono::Utc::now().timestamp() as u64;
+        let is_tdt_tot = tdt_tot.is_some();
+        let is_sdt = self.sdt_list.iter().any(|&(_, other)| ! other);
 
-        if ! self.present.items.is_empty() {
-            let event = self.present.items.first().unwrap();
-            if event.start + u64::from(event.duration) > current_time {
-                return;
+        let input_buffer = std::mem::take(&mut self.input_buffer);
+        let mut remux_buffer = std::mem::take(&mut self.remux_buffer);
+        let mut skip = 0;
+
+        while input_buffer.len() - skip >= ts::PACKET_SIZE {
+            let packet = &input_buffer[skip ..][.. ts::PACKET_SIZE];
+            if packet[0] != 0x47 {
+                // lost sync
+                skip += 1;
+                continue;
             }
-            self.present.items.remove(0);
-            self.schedule.items.remove(0);
+            skip += ts::PACKET_SIZE;
 
-            self.present.version = (self.present.version + 1) % 32;
-            self.schedule.version = (self.schedule.version + 1) % 32;
-        }
+            let pid = ts_pid(packet);
+            self.discovery.push(packet, pid);
+
+            if pid == psi::SDT_PID && is_sdt {
+                self.sdt_filter(packet, multiplex_list);
+            }
+
+            let is_slot = match pid {
+                NULL_PID | psi::EIT_PID => true,
+                psi::TDT_PID => is_tdt_tot,
+                psi::SDT_PID => is_sdt,
+                _ => false,
+            };
 
-        if self.present.items.is_empty() {
-            if let Some(item) = self.schedule.items.get(0) {
-                self.present.items.push(item.clone());
+            if ! is_slot {
+                remux_buffer.extend_from_slice(packet);
+            } else if self.clock.is_ready(now, packet_size) && self.next_packet(
+                service_list,
+                multiplex_list,
+                tdt_tot.as_deref_mut(),
+                current_time,
+                &mut remux_buffer)
+            {
+                self.clock.consume(packet_size);
             } else {
-                return;
+                remux_buffer.extend_from_slice(ts::NULL_PACKET);
             }
         }
 
-        let event = self.present.items.first().unwrap();
-        if event.start > current_time {
-            return;
-        }
+        self.input_buffer = input_buffer;
+        self.input_buffer.drain(.. skip);
+
+        self.check_overload();
 
-        if let Some(item) = self.schedule.items.get(1) {
-            self.present.items.push(item.clone());
+        let size = remux_buffer.len() - remux_buffer.len() % BLOCK_SIZE;
+        for block in remux_buffer[.. size].chunks(BLOCK_SIZE) {
+            self.output.send(block);
         }
+        self.output_meter.add(size);
+        remux_buffer.drain(.. size);
+        self.remux_buffer = remux_buffer;
+    }
+}
 
-        let event = self.present.items.first_mut().unwrap();
-        event.status = 4;
+
+/// Returns service identifier from config for the check report
+fn config_context(config: &Config) -> String {
+    let xmltv_id: &str = config.get("xmltv-id").unwrap_or("");
+    match config.get::<u16>("pnr") {
+        Some(pnr) if ! xmltv_id.is_empty() => format!("{}:{}", xmltv_id, pnr),
+        Some(pnr) => format!("{} (line {})", pnr, config.get_line()),
+        None if ! xmltv_id.is_empty() => xmltv_id.to_owned(),
+        None => format!("at line {}", config.get_line()),
     }
 }
 
@@ -418,6 +2717,14 @@ fn init_schema() -> Schema {
         s.len() == 3
     };
 
+    let timezone_validator = |s: &str| -> bool {
+        s.parse::<Tz>().is_ok()
+    };
+
+    let log_level_validator = |s: &str| -> bool {
+        log::Level::parse(s).is_some()
+    };
+
     let offset_validator = |s: &str| -> bool {
         if s.is_empty() { return false }
         match s.as_bytes()[0] {
@@ -435,8 +2742,10 @@ fn init_schema() -> Schema {
     let mut schema_service = Schema::new("service",
         "Service configuration. Multiplex contains one or more services");
     schema_service.set("pnr",
-        "Program Number. Required. Should be in range 1 .. 65535",
-        true, Schema::range(1 .. 65535));
+        "Program Number. Should be in range 1 .. 65535. \
+        Required if multiplex has no input. Otherwise service could be found \
+        in the input stream by name or by XMLTV channel name",
+        false, Schema::range(1 .. 65535));
     schema_service.set("xmltv-id",
         "Program indentifier in the XMLTV. Required",
         true, None);
@@ -450,14 +2759,25 @@ fn init_schema() -> Schema {
         "Change UTC time in the range between -720 minutes and +780 minutes. Default: 0",
         false, offset_validator);
     schema_service.set("parental-rating",
-        "Recommended minimum age of the end user. Should be in range 4 .. 18. Default: 0",
+        "Recommended minimum age of the end user. Should be in range 4 .. 18. \
+        Used if XMLTV has no rating for the country. Default: 0",
         false, Schema::range(4 .. 18));
+    schema_service.set("name",
+        "Service name for SDT. Default: xmltv-id",
+        false, None);
+    schema_service.set("provider",
+        "Service provider name for SDT. Default: empty",
+        false, None);
+    schema_service.set("service-type",
+        "Service type for SDT. Range 1 .. 255. Default: 1 - digital television",
+        false, Schema::range(1 .. 255));
 
     let mut schema_multiplex = Schema::new("multiplex",
         "Multiplex configuration. App contains one or more multiplexes");
     schema_multiplex.set("tsid",
-        "Transport Stream Identifier. Required. Range 0 .. 65535",
-        true, Schema::range(0 .. 65535));
+        "Transport Stream Identifier. Range 0 .. 65535. \
+        Required if multiplex has no input. Otherwise defined from the input stream",
+        false, Schema::range(0 .. 65535));
     schema_multiplex.set("codepage",
         "Redefine codepage for multiplex. Default: app codepage",
         false, codepage_validator);
@@ -467,6 +2787,38 @@ fn init_schema() -> Schema {
     schema_multiplex.set("utc-offset",
         "Change UTC time in the range between -720 minutes and +780 minutes. Default: 0",
         false, offset_validator);
+    schema_multiplex.set("output",
+        "Comma-separated list of addresses for multiplex own stream. \
+        Default: app output",
+        false, None);
+    schema_multiplex.set("input",
+        "Remux mode. Source of the transport stream. \
+        Generated tables are inserted instead of null packets. \
+        Example: udp://239.255.2.1:10000, udp://192.168.1.10@239.255.2.1:10000, \
+        file:///tmp/input.fifo",
+        false, None);
+    schema_multiplex.set("discover",
+        "Add services found in the input stream if XMLTV has channel \
+        with same name. Default: false",
+        false, None);
+    schema_multiplex.set("eit-rate",
+        "Limit EPG output bitrate in kbit/s for multiplex own stream. \
+        Range: 15 .. 20000. Default: app eit-rate",
+        false, Schema::range(15 .. 20000));
+    schema_multiplex.set("cbr",
+        "Stuff multiplex own stream with null packets up to eit-rate. \
+        Not used in remux mode. Default: app cbr",
+        false, None);
+    schema_multiplex.set("eit-other",
+        "Generate EIT other tables (p/f 0x4F and schedule 0x60 .. 0x6F) \
+        for services of other multiplexes. Default: false",
+        false, None);
+    schema_multiplex.set("sdt",
+        "Generate SDT actual table (0x42) for multiplex services. Default: false",
+        false, None);
+    schema_multiplex.set("sdt-other",
+        "Generate SDT other table (0x46) for services of other multiplexes. Default: false",
+        false, None);
     schema_multiplex.push(schema_service);
 
     let mut schema_tdt_tot = Schema::new("tdt-tot",
@@ -477,6 +2829,11 @@ fn init_schema() -> Schema {
     schema_tdt_tot.set("offset",
         "Offset time from UTC in the range between -720 minutes and +780 minutes. Default: 0",
         false, offset_validator);
+    schema_tdt_tot.set("timezone",
+        "Time zone name from the IANA database. Example: Europe/Tallinn. \
+        Offset and daylight saving time changes are calculated automatically. \
+        Redefines offset option",
+        false, timezone_validator);
 
     let mut schema = Schema::new("",
         "eit-stream - MPEG-TS EPG (Electronic Program Guide) streamer\n\
@@ -501,12 +2858,33 @@ fn init_schema() -> Schema {
         #\n\
         # General options:");
     schema.set("xmltv",
-        "Full path to XMLTV file or http/https address",
+        "Full path to XMLTV file or http/https address. \
+        Compressed XMLTV (gzip, xz) is supported for http/https",
+        false, None);
+    schema.set("xmltv-reload",
+        "Interval in minutes to reload XMLTV. \
+        Local files are also reloaded on modification. \
+        Default: 0 - disabled for local files, 60 minutes for http/https",
+        false, None);
+    schema.set("filler",
+        "Title of the synthetic present event for gaps between events. \
+        Default: disabled - present event is empty during gaps",
+        false, None);
+    schema.set("state",
+        "Path to the file to keep table versions and continuity counters \
+        between restarts. Default: disabled",
+        false, None);
+    schema.set("xmltv-cache",
+        "Directory to keep last downloaded copy of the http/https XMLTV. \
+        Gzip and xz compressed data is unpacked. \
+        Cached copy is used if server is not available. Default: system temporary directory",
         false, None);
     // TODO: udp address validator
     schema.set("output",
-        "UDP Address. Requried. Example: udp://239.255.1.1:10000",
-        true, None);
+        "Comma-separated list of UDP, RTP addresses or files. \
+        Requried if any multiplex has no own output. \
+        Example: udp://239.255.1.1:10000, rtp://239.255.1.2:10000?ssrc=1234",
+        false, None);
     schema.set("onid",
         "Original Network Identifier. Default: 1",
         false, None);
@@ -514,14 +2892,74 @@ fn init_schema() -> Schema {
         "EPG Codepage",
         false, codepage_validator);
     schema.set("eit-days",
-        "How many days includes into EPG schedule. Range: 1 .. 7. Default: 3",
-        false, Schema::range(1 .. 7));
+        "How many days includes into EPG schedule. Range: 1 .. 16. Default: 3",
+        false, Schema::range(1 .. 16));
     schema.set("eit-rate",
         "Limit EPG output bitrate in kbit/s. Range: 15 .. 20000. Default: 30 kbit/s per service",
         false, Schema::range(15 .. 20000));
+    schema.set("cbr",
+        "Stuff output with null packets up to eit-rate for constant bitrate. \
+        Not used in remux mode. Default: false",
+        false, None);
+    schema.set("log",
+        "Log destination: stderr, syslog, or path to the log file. \
+        File reopened on SIGHUP. Default: stderr",
+        false, None);
+    schema.set("log-level",
+        "Log level: error, warning, info, debug. Default: info",
+        false, log_level_validator);
+    schema.set("status",
+        "Address to listen for HTTP requests. \
+        Serves /metrics in the Prometheus text format and /status in JSON. \
+        Example: 127.0.0.1:8080. Applied on start",
+        false, None);
+    schema.set("rate-report",
+        "Interval in seconds to report measured output bitrate. \
+        0 to disable. Default: 300",
+        false, None);
+    schema.set("pf-interval",
+        "Maximum repetition interval in seconds for EIT present/following actual. \
+        Range: 1 .. 600. Default: 2",
+        false, Schema::range(1 .. 600));
+    schema.set("pf-other-interval",
+        "Maximum repetition interval in seconds for EIT present/following other. \
+        Range: 1 .. 600. Default: 10",
+        false, Schema::range(1 .. 600));
+    schema.set("schedule-interval",
+        "Maximum repetition interval in seconds for EIT schedule of the first day. \
+        Range: 1 .. 600. Default: 10",
+        false, Schema::range(1 .. 600));
+    schema.set("schedule-later-interval",
+        "Maximum repetition interval in seconds for EIT schedule of next days. \
+        Range: 1 .. 600. Default: 30",
+        false, Schema::range(1 .. 600));
+    schema.set("sdt-interval",
+        "Maximum repetition interval in seconds for SDT actual. \
+        Range: 1 .. 600. Default: 2",
+        false, Schema::range(1 .. 600));
+    schema.set("sdt-other-interval",
+        "Maximum repetition interval in seconds for SDT other. \
+        Range: 1 .. 600. Default: 10",
+        false, Schema::range(1 .. 600));
+    schema.set("tdt-tot-interval",
+        "Maximum repetition interval in seconds for TDT and TOT. \
+        Range: 1 .. 600. Default: 30",
+        false, Schema::range(1 .. 600));
     schema.set("utc-offset",
         "Change UTC time in the range between -720 minutes and +780 minutes. Default: 0",
         false, offset_validator);
+    schema.set("country",
+        "Country code in ISO 3166-1 alpha-3 format for parental rating. \
+        Used for service parental-rating and XMLTV ratings of unknown systems",
+        false, country_validator);
+    schema.set("short-text",
+        "Keep description trimmed on the word boundary in the short event descriptor \
+        if full description moved to the extended event descriptors. Default: false",
+        false, None);
+    schema.set("genre-map",
+        "Path to file with mapping of the XMLTV categories to the DVB content. \
+        Each line in format: category = 0x10. Extends built-in mapping",
+        false, None);
 
     schema.push(schema_tdt_tot);
     schema.push(schema_multiplex);
@@ -530,16 +2968,39 @@ fn init_schema() -> Schema {
 }
 
 
-fn load_config() -> Result<Config> {
-    use std::process::exit;
+/// Command line arguments
+#[derive(Debug, Default)]
+struct Args {
+    config_path: String,
+    /// Log level shift. Positive for `-v`, negative for `-q`
+    verbosity: i32,
+    /// Check configuration and exit
+    check: bool,
+    /// Print generated EIT and exit
+    dump: Option<DumpFormat>,
+    /// Input to analyze instead of streaming
+    analyze: Option<String>,
+}
 
-    let mut schema = init_schema();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DumpFormat {
+    Text,
+    Json,
+}
+
+
+/// Parses command line arguments
+fn parse_args() -> Args {
+    use std::process::exit;
 
     let mut args = std::env::args();
     let program = args.next().unwrap();
-    let arg = match args.next() {
-        Some(v) => match v.as_ref() {
-            "-v" | "--version" => {
+    let mut result = Args::default();
+
+    while let Some(v) = args.next() {
+        match v.as_ref() {
+            "-V" | "--version" => {
                 version();
                 exit(0);
             },
@@ -548,24 +3009,165 @@ fn load_config() -> Result<Config> {
                 exit(0);
             },
             "-H" => {
+                let schema = init_schema();
                 println!("Configuration file format:\n\n{}", &schema.info());
                 exit(0);
             },
-            _ => v,
-        },
-        None => {
-            usage(&program);
-            exit(0);
-        },
-    };
+            "analyze" if result.config_path.is_empty() && result.analyze.is_none() => {
+                match args.next() {
+                    Some(v) => result.analyze = Some(v),
+                    None => {
+                        error!("analyze requires input address");
+                        exit(1);
+                    }
+                }
+            },
+            "--check" => result.check = true,
+            "--dump" => {
+                result.dump = match args.next().as_deref() {
+                    Some("text") => Some(DumpFormat::Text),
+                    Some("json") => Some(DumpFormat::Json),
+                    _ => {
+                        error!("--dump requires format: text or json");
+                        exit(1);
+                    }
+                };
+            },
+            v if v.starts_with("-v") && v[1 ..].bytes().all(|c| c == b'v') => {
+                result.verbosity += (v.len() - 1) as i32;
+            },
+            v if v.starts_with("-q") && v[1 ..].bytes().all(|c| c == b'q') => {
+                result.verbosity -= (v.len() - 1) as i32;
+            },
+            _ => result.config_path = v,
+        }
+    }
+
+    if result.config_path.is_empty() && result.analyze.is_none() {
+        usage(&program);
+        exit(0);
+    }
+
+    result
+}
+
+
+/// Applies log options from config
+fn init_log(config: &Config, verbosity: i32) -> Result<()> {
+    let level = config.get("log-level")
+        .and_then(log::Level::parse)
+        .unwrap_or(log::Level::Info)
+        .shift(verbosity);
+    let target = config.get("log").unwrap_or("stderr");
+
+    log::init(level, target)
+        .map_err(|e| AppError::LogOpen(target.to_owned(), e))
+}
 
-    let config = Config::open(&arg)?;
+
+fn load_config(path: &str) -> Result<Config> {
+    let mut schema = init_schema();
+
+    let config = Config::open(path)?;
     schema.check(&config)?;
 
     Ok(config)
 }
 
 
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+
+extern "C" fn sighup_handler(_signum: libc::c_int) {
+    RELOAD.store(true, Ordering::Relaxed);
+}
+
+
+extern "C" fn stop_handler(_signum: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+
+fn init_signals() {
+    let handler = sighup_handler as extern "C" fn(libc::c_int);
+    let stop = stop_handler as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGHUP, handler as libc::sighandler_t);
+        libc::signal(libc::SIGINT, stop as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, stop as libc::sighandler_t);
+    }
+}
+
+
+/// Compares assembled tables
+fn is_table_equal<T: PsiDemux>(a: &T, b: &T) -> bool {
+    let a = a.psi_list_assemble();
+    let b = b.psi_list_assemble();
+
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.buffer == b.buffer)
+}
+
+
+/// Assembles table sections for the scheduler
+fn assemble_table(table: Table,
+    service_list: &mut [Service],
+    multiplex_list: &[Multiplex],
+    tdt_tot: Option<&mut TdtTot>,
+    current_time: u64) -> Vec<Psi>
+{
+    match table {
+        Table::TdtTot => {
+            tdt_tot.map(TdtTot::psi_list_assemble).unwrap_or_default()
+        }
+        Table::Sdt(multiplex_id, other) => {
+            let table_id = if other { 0x46 } else { 0x42 };
+            multiplex_list[multiplex_id].sdt_assemble(table_id)
+        }
+        Table::Present(service_id, other) => {
+            let service = &mut service_list[service_id];
+            service.update_present(current_time);
+
+            let table_id = if other { 0x4F } else { 0x4E };
+            service.present_assemble(table_id)
+        }
+        Table::Schedule(service_id, other) | Table::ScheduleLater(service_id, other) => {
+            let table_id = if other { 0x60 } else { 0x50 };
+
+            // sections of the segments in the first 24 hours from current time
+            let current_segment = (current_time % 86400 / SEGMENT_DURATION) as usize;
+            let is_later = matches!(table, Table::ScheduleLater(..));
+
+            service_list[service_id].schedule_assemble(table_id, current_time, |p| {
+                let segment = section::eit_segment(p);
+                (segment >= current_segment + SEGMENTS_PER_DAY) == is_later
+            })
+        }
+    }
+}
+
+
+#[inline]
+fn ts_pid(packet: &[u8]) -> u16 {
+    (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2])
+}
+
+
+/// Formats unix time for reports
+fn format_time(timestamp: u64) -> String {
+    chrono::Utc.timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+
+/// Compares service names ignoring case
+fn is_name_equal(a: &str, b: &str) -> bool {
+    ! a.is_empty() && a.to_lowercase() == b.to_lowercase()
+}
+
+
 fn fill_null_ts(dst: &mut Vec<u8>) {
     let remain = dst.len() % BLOCK_SIZE;
     if remain == 0 {
@@ -579,192 +3181,210 @@ fn fill_null_ts(dst: &mut Vec<u8>) {
 }
 
 
-fn wrap() -> Result<()> {
-    let config = load_config()?;
-
-    let mut instance = Instance {
-        onid: config.get("onid").unwrap_or(1),
-        codepage: config.get("codepage").unwrap_or(0),
-        eit_days: config.get("eit-days").unwrap_or(3),
-        eit_rate: config.get("eit-rate"),
-        utc_offset: config.get("utc-offset").map(parse_offset).unwrap_or(0),
-        country: config.get("country").unwrap_or("   ").to_owned(),
-        ..Default::default()
+/// Reads transport stream from input and prints analyzer report.
+/// File is analyzed until end, live input until stop signal
+fn analyze(addr: &str) -> Result<()> {
+    let addr = if addr.contains("://") {
+        addr.to_owned()
+    } else {
+        format!("file://{}", addr)
     };
 
-    match instance.open_xmltv(&config, usize::max_value())? {
-        Some(v) => instance.epg_item_id = v,
-        None => instance.epg_item_id = usize::max_value(),
-    };
+    let mut input = Input::open(&addr)?;
+    let is_file = matches!(input, Input::File(_));
 
-    match config.get("output") {
-        Some(v) => instance.open_output(v)?,
-        None => return Err(AppError::MissingOutput),
-    };
+    init_signals();
 
+    let mut analyzer = Analyzer::default();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut data = Vec::new();
 
-    for m in config.iter() {
-        match m.get_name() {
-            "multiplex" => instance.parse_config(m)?,
-            "tdt-tot" => instance.parse_tdt_tot(m)?,
-            _ => {}
+    let start = time::Instant::now();
+    let mut report_time = start;
+
+    while ! STOP.load(Ordering::Relaxed) {
+        let n = input.recv(&mut buffer)?;
+        if n == 0 {
+            if is_file {
+                break;
+            }
+            thread::sleep(INPUT_DELAY);
+            continue;
         }
-    }
 
-    // Prepare EIT from EPG
-    let now = chrono::Utc::now();
-    let current_time = now.timestamp() as u64;
-    let last_time = (now + chrono::Duration::days(instance.eit_days as i64)).timestamp() as u64;
+        data.extend_from_slice(&buffer[.. n]);
 
-    for service in &mut instance.service_list {
-        let epg = instance.epg_list.get_mut(service.epg_item_id).unwrap();
-        let epg_item = match epg.channels.get_mut(&service.xmltv_id) {
-            Some(v) => v,
-            None => {
-                println!("Warning: service \"{}\" not found in XMLTV", &service.xmltv_id);
-                continue;
-            },
+        // live input uses arrival time and local clock. File uses PCR only
+        let (time, local_time) = if is_file {
+            (None, None)
+        } else {
+            let local_time = time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .map(|v| v.as_secs_f64())
+                .ok();
+            (Some(start.elapsed().as_secs_f64()), local_time)
         };
 
-        // Present+Following
-        service.present.table_id = 0x4E;
-        service.present.pnr = service.pnr;
-        service.present.tsid = service.tsid;
-        service.present.onid = service.onid;
+        let mut skip = 0;
+        while data.len() - skip >= ts::PACKET_SIZE {
+            if data[skip] != 0x47 {
+                // lost sync
+                skip += 1;
+                continue;
+            }
+            analyzer.push(&data[skip ..][.. ts::PACKET_SIZE], time, local_time);
+            skip += ts::PACKET_SIZE;
+        }
+        data.drain(.. skip);
 
-        // Schedule
-        service.schedule.table_id = 0x50;
-        service.schedule.pnr = service.pnr;
-        service.schedule.tsid = service.tsid;
-        service.schedule.onid = service.onid;
+        if ! is_file && report_time.elapsed() >= ANALYZE_REPORT_INTERVAL {
+            report_time = time::Instant::now();
+            println!("{}", analyzer.report());
+        }
+    }
 
-        for event in &mut epg_item.events {
-            event.start = ((event.start as i64) - (service.utc_offset as i64) * 60) as u64;
-            event.stop = ((event.stop as i64) - (service.utc_offset as i64) * 60) as u64;
+    print!("{}", analyzer.report());
+    Ok(())
+}
 
-            if event.start > last_time {
-                break;
-            }
 
-            if event.stop > current_time {
-                event.codepage = service.codepage;
-
-                if service.parental_rating != 0 {
-                    let country = instance.country.as_bytes();
-                    if country.len() >= 3 {
-                        let country_bytes: [u8; 3] = [
-                            country[0],
-                            country[1],
-                            country[2],
-                        ];
-                        event.parental_rating.insert(
-                            country_bytes,
-                            service.parental_rating
-                        );
-                    }
-                }
+fn wrap() -> Result<()> {
+    let args = parse_args();
 
-                service.schedule.items.push(EitItem::from(&*event));
-            }
-        }
+    if let Some(addr) = &args.analyze {
+        log::init(log::Level::Info.shift(args.verbosity), "stderr")?;
+        return analyze(addr);
+    }
 
-        if service.schedule.items.is_empty() {
-            println!("Warning: service \"{}\" has empty list", &service.xmltv_id);
+    let config_path = &args.config_path;
+    let config = load_config(config_path)?;
+    init_log(&config, args.verbosity)?;
+
+    if args.check {
+        let instance = Instance::open(&config)?;
+        if ! instance.check() || log::error_count() != 0 {
+            std::process::exit(1);
         }
+        return Ok(());
     }
 
-    // Main loop
-
-    let mut eit_cc = 0;
+    if let Some(format) = args.dump {
+        let mut instance = Instance::open(&config)?;
+        instance.restore_state();
+        instance.load();
+        instance.dump(format);
+        return Ok(());
+    }
 
-    let rate_limit = instance.eit_rate.unwrap_or_else(|| {
-        instance.service_list.len() * 30
-    });
-    let rate_limit = rate_limit * 1000 / 8;
-    let pps = time::Duration::from_nanos(
-        1_000_000_000u64 * (BLOCK_SIZE as u64) / (rate_limit as u64)
-    );
+    let mut instance = Instance::open(&config)?;
+    instance.open_stream()?;
+    instance.restore_state();
+    instance.load();
+    instance.save_state();
+
+    let status = match config.get::<&str>("status") {
+        Some(addr) => {
+            let start_time = chrono::Utc::now().timestamp() as u64;
+            let status = Arc::new(Mutex::new(Status::new(start_time)));
+            status::spawn(addr, status.clone())
+                .map_err(|e| AppError::StatusListen(addr.to_owned(), e))?;
+            Some(status)
+        }
+        None => None,
+    };
 
+    init_signals();
 
-    let mut ts_buffer = Vec::<u8>::with_capacity(
-        instance.service_list.len() * ts::PACKET_SIZE * 20
-    );
+    // Main loop
 
-    let mut schedule_skip = 0;
+    let mut xmltv_check = time::Instant::now();
+    let mut status_update = time::Instant::now();
 
     loop {
-        if let Some(tdt_tot) = &mut instance.tdt_tot {
-            tdt_tot.demux(&mut ts_buffer);
-            fill_null_ts(&mut ts_buffer);
+        if STOP.load(Ordering::Relaxed) {
+            instance.save_state();
+            return Ok(());
         }
 
-        for service in &mut instance.service_list {
-            service.clear();
-
-            let mut present_psi_list = service.present.psi_list_assemble();
-            if present_psi_list.is_empty() {
-                continue;
+        if RELOAD.swap(false, Ordering::Relaxed) {
+            let result = load_config(config_path).and_then(|c| {
+                instance.reload(&c)?;
+                Ok(c)
+            });
+            match result {
+                Ok(c) => {
+                    // log settings applied only if configuration accepted
+                    if let Err(e) = init_log(&c, args.verbosity) {
+                        error!("{}", e);
+                    }
+                    info!("configuration reloaded");
+                    instance.save_state();
+                }
+                Err(e) => error!("failed to reload configuration [{}]", e),
             }
+        }
 
-            for p in &mut present_psi_list {
-                p.pid = psi::EIT_PID;
-                p.cc = eit_cc;
-                p.demux(&mut ts_buffer);
-                eit_cc = p.cc;
-
-                fill_null_ts(&mut ts_buffer);
-            }
+        if xmltv_check.elapsed() >= XMLTV_CHECK_INTERVAL {
+            xmltv_check = time::Instant::now();
+            instance.reload_xmltv();
+            instance.save_state();
         }
 
-        while schedule_skip < instance.service_list.len() {
-            let service = &instance.service_list[schedule_skip];
-            schedule_skip += 1;
+        let current_time = chrono::Utc::now().timestamp() as u64;
+        let now = time::Instant::now();
+        let mut next_send = now + IDLE_DELAY;
 
-            let mut schedule_psi_list = service.schedule.psi_list_assemble();
-            for p in &mut schedule_psi_list {
-                p.pid = psi::EIT_PID;
-                p.cc = eit_cc;
-                p.demux(&mut ts_buffer);
-                eit_cc = p.cc;
+        for stream in &mut instance.stream_list {
+            if let Some(interval) = instance.rate_report {
+                stream.report(now, interval);
+            }
 
-                fill_null_ts(&mut ts_buffer);
+            if stream.input.is_some() {
+                stream.remux(
+                    &mut instance.service_list,
+                    &instance.multiplex_list,
+                    instance.tdt_tot.as_mut(),
+                    current_time);
+                next_send = cmp::min(next_send, now + INPUT_DELAY);
+                continue;
             }
 
-            if ts_buffer.len() >= rate_limit {
-                break;
+            if stream.next_send <= now {
+                stream.process(
+                    &mut instance.service_list,
+                    &instance.multiplex_list,
+                    instance.tdt_tot.as_mut(),
+                    now,
+                    current_time);
             }
-        }
 
-        if schedule_skip == instance.service_list.len() {
-            schedule_skip = 0;
+            next_send = cmp::min(next_send, stream.next_send);
         }
 
-        if ts_buffer.is_empty() {
-            thread::sleep(IDLE_DELAY);
-            continue;
+        if instance.apply_discovery() {
+            instance.load();
         }
 
-        let mut skip = 0;
-        loop {
-            let pkt_len = cmp::min(ts_buffer.len() - skip, BLOCK_SIZE);
-            let next = skip + pkt_len;
-            instance.output.send(&ts_buffer[skip..next]).unwrap();
-            thread::sleep(pps);
-
-            if next < ts_buffer.len() {
-                skip = next;
-            } else {
-                break;
+        if let Some(status) = &status {
+            if status_update.elapsed() >= STATUS_UPDATE_INTERVAL {
+                status_update = time::Instant::now();
+                if let Ok(mut status) = status.lock() {
+                    instance.update_status(&mut status, status_update);
+                }
             }
         }
 
-        ts_buffer.clear();
+        let now = time::Instant::now();
+        if next_send > now {
+            thread::sleep(next_send - now);
+        }
     }
 }
 
 
 fn main() {
     if let Err(e) = wrap() {
-        println!("{}", e.to_string());
+        error!("{}", e);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,146 @@
+/// Rating system name, country code, list of the rating values with minimum age
+type RatingSystem = (&'static str, &'static str, &'static [(&'static str, u8)]);
+
+
+/// Known XMLTV rating systems and country codes (ISO 3166-1 alpha-3).
+/// Numeric values like "16", "16+", "-16" parsed as is
+const SYSTEM_LIST: &[RatingSystem] = &[
+    ("MPAA", "USA", &[
+        ("G", 0),
+        ("PG", 10),
+        ("PG-13", 13),
+        ("R", 17),
+        ("NC-17", 18),
+    ]),
+    ("VCHIP", "USA", &[
+        ("TV-Y", 0),
+        ("TV-Y7", 7),
+        ("TV-G", 0),
+        ("TV-PG", 10),
+        ("TV-14", 14),
+        ("TV-MA", 17),
+    ]),
+    ("BBFC", "GBR", &[
+        ("U", 0),
+        ("PG", 8),
+        ("12A", 12),
+        ("R18", 18),
+    ]),
+    ("FSK", "DEU", &[]),
+    ("CSA", "FRA", &[]),
+    ("KIJKWIJZER", "NLD", &[
+        ("AL", 0),
+    ]),
+    ("RARS", "RUS", &[]),
+    ("ACMA", "AUS", &[
+        ("G", 0),
+        ("PG", 8),
+        ("M", 15),
+        ("MA15+", 15),
+        ("AV15+", 15),
+        ("R18+", 18),
+    ]),
+];
+
+
+/// Country codes (ISO 3166-1 alpha-3) accepted as rating system name. Sorted
+const COUNTRY_LIST: &[&str] = &[
+    "ABW", "AFG", "AGO", "AIA", "ALA", "ALB", "AND", "ARE", "ARG", "ARM", "ASM", "ATA",
+    "ATF", "ATG", "AUS", "AUT", "AZE", "BDI", "BEL", "BEN", "BES", "BFA", "BGD", "BGR",
+    "BHR", "BHS", "BIH", "BLM", "BLR", "BLZ", "BMU", "BOL", "BRA", "BRB", "BRN", "BTN",
+    "BVT", "BWA", "CAF", "CAN", "CCK", "CHE", "CHL", "CHN", "CIV", "CMR", "COD", "COG",
+    "COK", "COL", "COM", "CPV", "CRI", "CUB", "CUW", "CXR", "CYM", "CYP", "CZE", "DEU",
+    "DJI", "DMA", "DNK", "DOM", "DZA", "ECU", "EGY", "ERI", "ESH", "ESP", "EST", "ETH",
+    "FIN", "FJI", "FLK", "FRA", "FRO", "FSM", "GAB", "GBR", "GEO", "GGY", "GHA", "GIB",
+    "GIN", "GLP", "GMB", "GNB", "GNQ", "GRC", "GRD", "GRL", "GTM", "GUF", "GUM", "GUY",
+    "HKG", "HMD", "HND", "HRV", "HTI", "HUN", "IDN", "IMN", "IND", "IOT", "IRL", "IRN",
+    "IRQ", "ISL", "ISR", "ITA", "JAM", "JEY", "JOR", "JPN", "KAZ", "KEN", "KGZ", "KHM",
+    "KIR", "KNA", "KOR", "KWT", "LAO", "LBN", "LBR", "LBY", "LCA", "LIE", "LKA", "LSO",
+    "LTU", "LUX", "LVA", "MAC", "MAF", "MAR", "MCO", "MDA", "MDG", "MDV", "MEX", "MHL",
+    "MKD", "MLI", "MLT", "MMR", "MNE", "MNG", "MNP", "MOZ", "MRT", "MSR", "MTQ", "MUS",
+    "MWI", "MYS", "MYT", "NAM", "NCL", "NER", "NFK", "NGA", "NIC", "NIU", "NLD", "NOR",
+    "NPL", "NRU", "NZL", "OMN", "PAK", "PAN", "PCN", "PER", "PHL", "PLW", "PNG", "POL",
+    "PRI", "PRK", "PRT", "PRY", "PSE", "PYF", "QAT", "REU", "ROU", "RUS", "RWA", "SAU",
+    "SDN", "SEN", "SGP", "SGS", "SHN", "SJM", "SLB", "SLE", "SLV", "SMR", "SOM", "SPM",
+    "SRB", "SSD", "STP", "SUR", "SVK", "SVN", "SWE", "SWZ", "SXM", "SYC", "SYR", "TCA",
+    "TCD", "TGO", "THA", "TJK", "TKL", "TKM", "TLS", "TON", "TTO", "TUN", "TUR", "TUV",
+    "TWN", "TZA", "UGA", "UKR", "UMI", "URY", "USA", "UZB", "VAT", "VCT", "VEN", "VGB",
+    "VIR", "VNM", "VUT", "WLF", "WSM", "YEM", "ZAF", "ZMB", "ZWE",
+];
+
+
+/// Minimum age defined in the DVB parental rating descriptor
+const AGE_MIN: u8 = 4;
+/// Maximum age defined in the DVB parental rating descriptor
+const AGE_MAX: u8 = 18;
+
+
+/// Converts country code into 3 bytes
+pub fn country_code(s: &str) -> Option<[u8; 3]> {
+    let s = s.as_bytes();
+    if s.len() == 3 && s.iter().all(u8::is_ascii_alphabetic) {
+        Some([
+            s[0].to_ascii_uppercase(),
+            s[1].to_ascii_uppercase(),
+            s[2].to_ascii_uppercase(),
+        ])
+    } else {
+        None
+    }
+}
+
+
+/// Returns country code if rating system name is a known ISO 3166-1 alpha-3 code
+fn system_country(system: &str) -> Option<[u8; 3]> {
+    let code = country_code(system)?;
+    let name = std::str::from_utf8(&code).ok()?;
+    COUNTRY_LIST.binary_search(&name).ok().map(|_| code)
+}
+
+
+/// Returns true if rating system is not defined, known, or a country code
+pub fn is_known(system: &str) -> bool {
+    let system = system.trim();
+    system.is_empty() ||
+        SYSTEM_LIST.iter().any(|(name, _, _)| name.eq_ignore_ascii_case(system)) ||
+        system_country(system).is_some()
+}
+
+
+/// Parses age from numeric rating value. Example: "16", "16+", "-16", "FSK 16"
+fn parse_age(value: &str) -> Option<u8> {
+    let value = value.trim_start_matches(|c: char| ! c.is_ascii_digit());
+    let end = value.find(|c: char| ! c.is_ascii_digit()).unwrap_or(value.len());
+    value[.. end].parse::<u8>().ok()
+}
+
+
+/// Converts XMLTV rating into country code and minimum age.
+/// Country code is None if rating system is not defined.
+/// Returns None if rating system or value is unknown or not restricted
+pub fn parse(system: &str, value: &str) -> Option<(Option<[u8; 3]>, u8)> {
+    let system = system.trim();
+    let value = value.trim();
+
+    let known = SYSTEM_LIST.iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(system));
+
+    let (country, age) = match known {
+        Some(&(_, country, value_list)) => {
+            let age = value_list.iter()
+                .find(|(v, _)| v.eq_ignore_ascii_case(value))
+                .map(|&(_, age)| age)
+                .or_else(|| parse_age(value))?;
+            (country_code(country), age)
+        }
+        None if system.is_empty() => (None, parse_age(value)?),
+        // some XMLTV sources use country code as system name
+        None => (Some(system_country(system)?), parse_age(value)?),
+    };
+
+    if age < AGE_MIN {
+        return None;
+    }
+
+    Some((country, std::cmp::min(age, AGE_MAX)))
+}
@@ -0,0 +1,355 @@
+use {
+    std::mem,
+
+    mpegts::psi::{
+        Psi,
+        PsiDemux,
+        Eit,
+        EitItem,
+    },
+
+    crate::section,
+};
+
+
+/// EIT schedule segment duration in seconds
+pub const SEGMENT_DURATION: u64 = 3 * 3600;
+/// Number of segments in the one schedule sub-table. 4 days
+pub const SEGMENTS_PER_TABLE: usize = 32;
+/// Number of segments in the one day
+pub const SEGMENTS_PER_DAY: usize = (86400 / SEGMENT_DURATION) as usize;
+/// Maximum number of sections in the one segment
+pub const SECTIONS_PER_SEGMENT: usize = 8;
+/// Maximum number of schedule sub-tables: 0x50 .. 0x5F or 0x60 .. 0x6F
+pub const SCHEDULE_TABLES: usize = 16;
+
+
+/// Assembled EIT schedule sub-tables of the one service.
+/// Sections are kept with table_id from 0x50 and rebuilt
+/// only if events or current day have been changed
+#[derive(Debug, Default)]
+pub struct ScheduleTables {
+    /// Sections by sub-table
+    table_list: Vec<Vec<Psi>>,
+    /// Midnight (UTC) of the first segment. 0 if not assembled yet
+    midnight: u64,
+    is_changed: bool,
+    /// Versions by sub-table
+    pub version_list: [u8; SCHEDULE_TABLES],
+    /// Events not fit into sections of the schedule segments
+    pub dropped_events: usize,
+}
+
+
+impl ScheduleTables {
+    /// Marks sections to be assembled on next update
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.is_changed = true;
+    }
+
+    /// Assembles sections if events or current day have been changed.
+    /// Version of the sub-table changed only if its sections have been changed.
+    /// Returns true if sections have been assembled
+    pub fn update(&mut self, schedule: &Eit, current_time: u64) -> bool {
+        let midnight = current_time - current_time % 86400;
+        if ! self.is_changed && self.midnight == midnight {
+            return false;
+        }
+
+        let (mut table_list, dropped_events) = assemble(schedule, midnight);
+
+        for (table_num, psi_list) in table_list.iter_mut().enumerate() {
+            set_version(psi_list, self.version_list[table_num]);
+
+            let is_changed = match self.table_list.get(table_num) {
+                Some(v) => ! is_sections_equal(v, psi_list),
+                None => true,
+            };
+
+            // first assembling keeps versions defined on restore
+            if is_changed && self.midnight != 0 {
+                let version = (self.version_list[table_num] + 1) % 32;
+                self.version_list[table_num] = version;
+                set_version(psi_list, version);
+            }
+        }
+
+        self.table_list = table_list;
+        self.midnight = midnight;
+        self.is_changed = false;
+        self.dropped_events = dropped_events;
+
+        true
+    }
+
+    /// Returns sections accepted with `filter`.
+    /// `table_id` is 0x50 for actual or 0x60 for other transport stream
+    pub fn sections<F>(&self, table_id: u8, filter: F) -> Vec<Psi>
+    where
+        F: Fn(&Psi) -> bool,
+    {
+        let mut psi_list = Vec::new();
+
+        for p in self.table_list.iter().flatten().filter(|p| filter(p)) {
+            let mut p = p.clone();
+            if table_id != 0x50 {
+                let offset = table_id - 0x50;
+                let table_id = p.buffer[0] + offset;
+                let last_table_id = p.buffer[13] + offset;
+                section::set_table_id(&mut p, table_id);
+                section::eit_set_last_table_id(&mut p, last_table_id);
+                section::finalize(&mut p);
+            }
+            psi_list.push(p);
+        }
+
+        psi_list
+    }
+}
+
+
+/// Sets version and CRC for all sections of the sub-table
+fn set_version(psi_list: &mut [Psi], version: u8) {
+    for p in psi_list {
+        section::set_version(p, version);
+        section::finalize(p);
+    }
+}
+
+
+fn is_sections_equal(a: &[Psi], b: &[Psi]) -> bool {
+    a.len() == b.len() &&
+        a.iter().zip(b).all(|(a, b)| a.buffer[.. section::size(a)] == b.buffer[.. section::size(b)])
+}
+
+
+/// Assembles EIT schedule sub-tables with table_id from 0x50.
+/// Events distributed over 3-hour segments. First segment begins at `midnight`.
+/// Segments are transmitted up to the last segment with events,
+/// empty segment is one section without events.
+/// Returns sections by sub-table and number of events not fit into segments.
+/// Sections should be finalized after
+pub fn assemble(schedule: &Eit, midnight: u64) -> (Vec<Vec<Psi>>, usize) {
+    let mut segment_list: Vec<Vec<EitItem>> = Vec::new();
+    for item in &schedule.items {
+        let segment = (item.start.saturating_sub(midnight) / SEGMENT_DURATION) as usize;
+        if segment >= SEGMENTS_PER_TABLE * SCHEDULE_TABLES {
+            break;
+        }
+        if segment >= segment_list.len() {
+            segment_list.resize_with(segment + 1, Vec::new);
+        }
+        segment_list[segment].push(item.clone());
+    }
+
+    let mut table_list = Vec::new();
+    let mut dropped_events = 0;
+    if segment_list.is_empty() {
+        return (table_list, dropped_events);
+    }
+
+    let table_count = segment_list.len().div_ceil(SEGMENTS_PER_TABLE);
+    let last_table_id = 0x50 + (table_count - 1) as u8;
+
+    let mut eit = Eit {
+        pnr: schedule.pnr,
+        tsid: schedule.tsid,
+        onid: schedule.onid,
+        ..Default::default()
+    };
+
+    for (table_num, table) in segment_list.chunks_mut(SEGMENTS_PER_TABLE).enumerate() {
+        eit.table_id = 0x50 + table_num as u8;
+
+        // sub-table without events has one empty section
+        let segment_count = table.iter()
+            .rposition(|v| ! v.is_empty())
+            .map_or(1, |v| v + 1);
+
+        let mut psi_list = Vec::new();
+        let mut last_section_number = 0;
+
+        for (segment_num, segment) in table[.. segment_count].iter_mut().enumerate() {
+            eit.items = mem::take(segment);
+
+            let mut segment_psi_list = eit.psi_list_assemble();
+            if segment_psi_list.len() > SECTIONS_PER_SEGMENT {
+                dropped_events += segment_psi_list[SECTIONS_PER_SEGMENT ..].iter()
+                    .map(section::eit_event_count)
+                    .sum::<usize>();
+                segment_psi_list.truncate(SECTIONS_PER_SEGMENT);
+            }
+
+            if segment_psi_list.is_empty() {
+                continue;
+            }
+
+            let first_section_number = (segment_num * SECTIONS_PER_SEGMENT) as u8;
+            let segment_last_section_number =
+                first_section_number + (segment_psi_list.len() - 1) as u8;
+
+            // last_section_number defined when all segments assembled
+            for (i, p) in segment_psi_list.iter_mut().enumerate() {
+                section::eit_set_number(p,
+                    first_section_number + i as u8,
+                    0,
+                    segment_last_section_number,
+                    last_table_id);
+            }
+
+            last_section_number = segment_last_section_number;
+            psi_list.append(&mut segment_psi_list);
+        }
+
+        for p in &mut psi_list {
+            section::set_last_section_number(p, last_section_number);
+        }
+
+        table_list.push(psi_list);
+    }
+
+    (table_list, dropped_events)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        mpegts::{
+            psi::Desc4D,
+            textcode::StringDVB,
+        },
+    };
+
+
+    const MIDNIGHT: u64 = 1_600_000_000 - 1_600_000_000 % 86400;
+
+
+    /// Builds event with text of `size` bytes
+    fn event(start: u64, size: usize) -> EitItem {
+        let mut item = EitItem {
+            event_id: (start / 60) as u16,
+            start,
+            duration: 60,
+            ..Default::default()
+        };
+        item.descriptors.push(Desc4D {
+            lang: StringDVB::from_str("eng", 0),
+            name: StringDVB::from_str("Event", 0),
+            text: StringDVB::from_str(&"x".repeat(size), 0),
+        });
+        item
+    }
+
+
+    fn schedule(items: Vec<EitItem>) -> Eit {
+        Eit {
+            table_id: 0x50,
+            pnr: 1,
+            tsid: 1,
+            onid: 1,
+            items,
+            ..Default::default()
+        }
+    }
+
+
+    /// Returns section_number, last_section_number, segment_last_section_number, last_table_id
+    fn numbers(psi_list: &[Psi]) -> Vec<(u8, u8, u8, u8)> {
+        psi_list.iter()
+            .map(|p| (p.buffer[6], p.buffer[7], p.buffer[12], p.buffer[13]))
+            .collect()
+    }
+
+
+    #[test]
+    fn test_numbering() {
+        let mut items = vec![event(MIDNIGHT + 2 * SEGMENT_DURATION, 10)];
+        // about 18 events per section. overflows 8 sections of the segment
+        for i in 0 .. 200 {
+            items.push(event(MIDNIGHT + 5 * SEGMENT_DURATION + i * 50, 200));
+        }
+        // second sub-table, segment 1
+        items.push(event(MIDNIGHT + 33 * SEGMENT_DURATION, 10));
+
+        let (table_list, dropped_events) = assemble(&schedule(items), MIDNIGHT);
+        assert_eq!(table_list.len(), 2);
+        assert!(dropped_events > 0);
+
+        let first = numbers(&table_list[0]);
+        assert_eq!(first.len(), 5 + SECTIONS_PER_SEGMENT);
+        // empty segments 0, 1, 3, 4 have one section without events
+        assert_eq!(&first[.. 5], &[
+            (0, 47, 0, 0x51),
+            (8, 47, 8, 0x51),
+            (16, 47, 16, 0x51),
+            (24, 47, 24, 0x51),
+            (32, 47, 32, 0x51),
+        ]);
+        for (i, v) in first[5 ..].iter().enumerate() {
+            assert_eq!(*v, (40 + i as u8, 47, 47, 0x51));
+        }
+        assert_eq!(section::eit_event_count(&table_list[0][0]), 0);
+        assert_eq!(section::eit_event_count(&table_list[0][2]), 1);
+
+        let second = numbers(&table_list[1]);
+        assert_eq!(second, vec![
+            (0, 8, 0, 0x51),
+            (8, 8, 8, 0x51),
+        ]);
+        assert_eq!(table_list[1][0].buffer[0], 0x51);
+    }
+
+
+    #[test]
+    fn test_empty_table() {
+        // first sub-table has no events
+        let items = vec![event(MIDNIGHT + 40 * SEGMENT_DURATION, 10)];
+
+        let (table_list, _) = assemble(&schedule(items), MIDNIGHT);
+        assert_eq!(table_list.len(), 2);
+        assert_eq!(numbers(&table_list[0]), vec![(0, 0, 0, 0x51)]);
+        assert_eq!(table_list[1].len(), 9);
+    }
+
+
+    #[test]
+    fn test_version() {
+        let current_time = MIDNIGHT + 3600;
+        let mut items = vec![
+            event(MIDNIGHT + 2 * 3600, 10),
+            event(MIDNIGHT + 3 * 3600, 10),
+        ];
+        items.push(event(MIDNIGHT + 40 * SEGMENT_DURATION, 10));
+
+        let mut tables = ScheduleTables {
+            version_list: [5; SCHEDULE_TABLES],
+            ..Default::default()
+        };
+
+        tables.invalidate();
+        assert!(tables.update(&schedule(items.clone()), current_time));
+        assert_eq!(&tables.version_list[.. 2], &[5, 5]);
+        assert_eq!(tables.sections(0x50, |_| true)[0].buffer[5], 0xC1 | (5 << 1));
+
+        // not changed
+        assert!(! tables.update(&schedule(items.clone()), current_time + 60));
+        tables.invalidate();
+        assert!(tables.update(&schedule(items.clone()), current_time + 60));
+        assert_eq!(&tables.version_list[.. 2], &[5, 5]);
+
+        // first event finished. only first sub-table changed
+        items.remove(0);
+        tables.invalidate();
+        assert!(tables.update(&schedule(items.clone()), current_time + 3600));
+        assert_eq!(&tables.version_list[.. 2], &[6, 5]);
+
+        // sections for other transport stream
+        let psi_list = tables.sections(0x60, |p| section::eit_segment(p) >= SEGMENTS_PER_TABLE);
+        assert_eq!(psi_list.len(), 9);
+        assert!(psi_list.iter().all(|p| p.buffer[0] == 0x61 && p.buffer[13] == 0x61));
+        assert_eq!(section::crc32(&psi_list[0].buffer[.. section::size(&psi_list[0])]), 0);
+    }
+}
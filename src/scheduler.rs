@@ -0,0 +1,176 @@
+use {
+    std::{
+        collections::VecDeque,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+
+    mpegts::psi::Psi,
+};
+
+
+/// Table announced in the stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Table {
+    TdtTot,
+    /// SDT. Multiplex id and true for other table
+    Sdt(usize, bool),
+    /// EIT present/following. Service id and true for other table
+    Present(usize, bool),
+    /// EIT schedule for the first day. Service id and true for other table
+    Schedule(usize, bool),
+    /// EIT schedule for next days. Service id and true for other table
+    ScheduleLater(usize, bool),
+}
+
+
+impl Table {
+    #[inline]
+    fn is_schedule(self) -> bool {
+        matches!(self, Table::Schedule(..) | Table::ScheduleLater(..))
+    }
+}
+
+
+/// Maximum repetition intervals for each table type.
+/// Default values defined in the TS 101 211
+#[derive(Debug, Clone)]
+pub struct Intervals {
+    pub present: Duration,
+    pub present_other: Duration,
+    pub schedule: Duration,
+    pub schedule_later: Duration,
+    pub sdt: Duration,
+    pub sdt_other: Duration,
+    pub tdt_tot: Duration,
+}
+
+
+impl Default for Intervals {
+    fn default() -> Self {
+        Intervals {
+            present: Duration::from_secs(2),
+            present_other: Duration::from_secs(10),
+            schedule: Duration::from_secs(10),
+            schedule_later: Duration::from_secs(30),
+            sdt: Duration::from_secs(2),
+            sdt_other: Duration::from_secs(10),
+            tdt_tot: Duration::from_secs(30),
+        }
+    }
+}
+
+
+impl Intervals {
+    fn get(&self, table: Table) -> Duration {
+        match table {
+            Table::TdtTot => self.tdt_tot,
+            Table::Sdt(_, false) => self.sdt,
+            Table::Sdt(_, true) => self.sdt_other,
+            Table::Present(_, false) => self.present,
+            Table::Present(_, true) => self.present_other,
+            Table::Schedule(..) => self.schedule,
+            Table::ScheduleLater(..) => self.schedule_later,
+        }
+    }
+}
+
+
+#[derive(Debug)]
+struct Task {
+    table: Table,
+    interval: Duration,
+    /// Time to start next repetition of the table
+    deadline: Instant,
+    /// Not sent sections of the current repetition
+    backlog: VecDeque<Psi>,
+}
+
+
+/// Selects sections to send with earliest deadline first.
+/// Tables repeated not later than defined interval if output rate is enough.
+/// Remaining bandwidth is used to repeat schedule tables more often
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    task_list: Vec<Task>,
+    /// Some tables were not sent in their interval
+    is_overload: bool,
+}
+
+
+impl Scheduler {
+    pub fn new(table_list: &[Table], intervals: &Intervals) -> Self {
+        let now = Instant::now();
+
+        let task_list = table_list.iter().map(|&table| Task {
+            table,
+            interval: intervals.get(table),
+            deadline: now,
+            backlog: VecDeque::new(),
+        }).collect();
+
+        Scheduler {
+            task_list,
+            is_overload: false,
+        }
+    }
+
+    /// Returns true if output rate is not enough to keep repetition intervals
+    #[inline]
+    pub fn is_overload(&self) -> bool {
+        self.is_overload
+    }
+
+    /// Returns time when next table should be repeated
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.task_list.iter().map(|t| t.deadline).min()
+    }
+
+    /// Returns next section to send.
+    /// Sections of the table prepared with `assemble` on each repetition
+    pub fn next<F>(&mut self, now: Instant, mut assemble: F) -> Option<(Table, Psi)>
+    where
+        F: FnMut(Table) -> Vec<Psi>,
+    {
+        // each table assembled once per call if it has no sections
+        for _ in 0 ..= self.task_list.len() {
+            let mut is_overload = false;
+            let mut urgent: Option<usize> = None;
+            let mut spare: Option<usize> = None;
+
+            for (i, task) in self.task_list.iter().enumerate() {
+                let is_due = task.deadline <= now;
+                if is_due && ! task.backlog.is_empty() {
+                    is_overload = true;
+                }
+
+                if is_due || ! task.backlog.is_empty() {
+                    if urgent.is_none_or(|u| task.deadline < self.task_list[u].deadline) {
+                        urgent = Some(i);
+                    }
+                } else if task.table.is_schedule()
+                    && spare.is_none_or(|s| task.deadline < self.task_list[s].deadline)
+                {
+                    spare = Some(i);
+                }
+            }
+
+            self.is_overload = is_overload;
+
+            let task = &mut self.task_list[urgent.or(spare)?];
+
+            if task.backlog.is_empty() {
+                task.backlog = assemble(task.table).into();
+                task.deadline = now + task.interval;
+            }
+
+            if let Some(psi) = task.backlog.pop_front() {
+                return Some((task.table, psi));
+            }
+        }
+
+        None
+    }
+}
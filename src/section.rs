@@ -0,0 +1,132 @@
+use mpegts::psi::Psi;
+
+
+/// Calculates MPEG-2 CRC32
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc ^= u32::from(b) << 24;
+        for _ in 0 .. 8 {
+            crc = if (crc & 0x8000_0000) != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+
+/// Returns full section size: header, payload and CRC
+#[inline]
+pub fn size(psi: &Psi) -> usize {
+    3 + ((usize::from(psi.buffer[1] & 0x0F) << 8) | usize::from(psi.buffer[2]))
+}
+
+
+/// Recalculates CRC after section modification
+pub fn finalize(psi: &mut Psi) {
+    let size = size(psi);
+    let crc = crc32(&psi.buffer[.. size - 4]);
+    psi.buffer[size - 4 ..][.. 4].copy_from_slice(&crc.to_be_bytes());
+}
+
+
+/// Sets EIT section numbering. Section should be finalized after
+pub fn eit_set_number(psi: &mut Psi,
+    section_number: u8,
+    last_section_number: u8,
+    segment_last_section_number: u8,
+    last_table_id: u8)
+{
+    psi.buffer[6] = section_number;
+    psi.buffer[7] = last_section_number;
+    psi.buffer[12] = segment_last_section_number;
+    psi.buffer[13] = last_table_id;
+}
+
+
+/// Sets last_section_number. Section should be finalized after
+#[inline]
+pub fn set_last_section_number(psi: &mut Psi, last_section_number: u8) {
+    psi.buffer[7] = last_section_number;
+}
+
+
+/// Sets table_id. Section should be finalized after
+#[inline]
+pub fn set_table_id(psi: &mut Psi, table_id: u8) {
+    psi.buffer[0] = table_id;
+}
+
+
+/// Sets version_number. Section should be finalized after
+#[inline]
+pub fn set_version(psi: &mut Psi, version: u8) {
+    psi.buffer[5] = (psi.buffer[5] & 0xC1) | ((version & 0x1F) << 1);
+}
+
+
+/// Sets EIT last_table_id. Section should be finalized after
+#[inline]
+pub fn eit_set_last_table_id(psi: &mut Psi, last_table_id: u8) {
+    psi.buffer[13] = last_table_id;
+}
+
+
+/// Returns number of events in the EIT section
+pub fn eit_event_count(psi: &Psi) -> usize {
+    let end = size(psi) - 4;
+    let mut skip = 14;
+    let mut count = 0;
+
+    while skip + 12 <= end {
+        let item = &psi.buffer[skip ..];
+        let desc_len = (usize::from(item[10] & 0x0F) << 8) | usize::from(item[11]);
+        skip += 12 + desc_len;
+        count += 1;
+    }
+
+    count
+}
+
+
+/// Returns EIT schedule segment number from the first segment of the first sub-table
+pub fn eit_segment(psi: &Psi) -> usize {
+    let table_num = usize::from(psi.buffer[0] & 0x0F);
+    let section_number = usize::from(psi.buffer[6]);
+    table_num * 32 + section_number / 8
+}
+
+
+/// Assembles PSI sections from TS packets of the one PID with mpegts `Psi`.
+/// Sections with invalid CRC are counted, TDT has no CRC and accepted as is
+#[derive(Debug, Default)]
+pub struct SectionReader {
+    psi: Psi,
+    /// Number of sections dropped due to invalid CRC
+    pub crc_errors: u64,
+}
+
+
+impl SectionReader {
+    /// Appends TS packet. Complete section with valid CRC moved to `dst`
+    pub fn push(&mut self, packet: &[u8], dst: &mut Vec<Vec<u8>>) {
+        self.psi.mux(packet);
+
+        let psi = &mut self.psi;
+        if psi.buffer.len() < 3 || psi.buffer.len() < size(psi) {
+            return;
+        }
+
+        let size = size(psi);
+        if psi.buffer[0] == 0x70 || psi.check() {
+            dst.push(psi.buffer[.. size].to_vec());
+        } else if psi.buffer[0] != 0xFF {
+            self.crc_errors += 1;
+        }
+
+        psi.buffer.clear();
+    }
+}
@@ -0,0 +1,161 @@
+use {
+    std::{
+        io::{
+            self,
+            BufRead,
+            BufReader,
+            BufWriter,
+            Write,
+        },
+        fs::{
+            self,
+            File,
+        },
+        collections::HashMap,
+    },
+
+    crate::schedule::SCHEDULE_TABLES,
+};
+
+
+/// Service key: onid, tsid, pnr
+pub type ServiceKey = (u16, u16, u16);
+/// Multiplex key: onid, tsid
+pub type MultiplexKey = (u16, u16);
+
+
+/// Table versions of the service
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServiceState {
+    pub present_version: u8,
+    /// Versions by schedule sub-table
+    pub schedule_version: [u8; SCHEDULE_TABLES],
+}
+
+
+/// Continuity counters of the output stream
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StreamState {
+    pub eit_cc: u8,
+    pub sdt_cc: u8,
+    pub tdt_cc: u8,
+}
+
+
+/// Runtime state kept between restarts.
+/// File format is a text with one item per line:
+///
+/// ```text
+/// service <onid> <tsid> <pnr> <present version> <schedule version> ...
+/// sdt <onid> <tsid> <version>
+/// stream <eit cc> <sdt cc> <tdt cc> <output>
+/// ```
+#[derive(Debug, Default)]
+pub struct State {
+    pub service_map: HashMap<ServiceKey, ServiceState>,
+    pub sdt_map: HashMap<MultiplexKey, u8>,
+    pub stream_map: HashMap<String, StreamState>,
+}
+
+
+/// Parses list of numbers. Returns None if any value is invalid
+fn parse_list<T: std::str::FromStr>(list: &[&str]) -> Option<Vec<T>> {
+    list.iter().map(|v| v.parse::<T>().ok()).collect()
+}
+
+
+impl State {
+    /// Loads state from file. Invalid lines are skipped
+    pub fn load(path: &str) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let mut state = State::default();
+
+        for line in file.lines() {
+            let line = line?;
+            let mut item = line.trim().splitn(2, ' ');
+            let kind = item.next().unwrap_or("");
+            let value = item.next().unwrap_or("");
+
+            match kind {
+                "service" => {
+                    // one schedule version for all sub-tables in previous format
+                    let value_list: Vec<&str> = value.split_whitespace().collect();
+                    if value_list.len() == 5 || value_list.len() == 4 + SCHEDULE_TABLES {
+                        let key = parse_list::<u16>(&value_list[.. 3]);
+                        let value = parse_list::<u8>(&value_list[3 ..]);
+                        if let (Some(key), Some(value)) = (key, value) {
+                            let mut schedule_version = [0; SCHEDULE_TABLES];
+                            for (i, version) in schedule_version.iter_mut().enumerate() {
+                                *version = value[1 + i % (value.len() - 1)] % 32;
+                            }
+                            state.service_map.insert((key[0], key[1], key[2]), ServiceState {
+                                present_version: value[0] % 32,
+                                schedule_version,
+                            });
+                        }
+                    }
+                }
+                "sdt" => {
+                    let value_list: Vec<&str> = value.split_whitespace().collect();
+                    if let [onid, tsid, version] = value_list.as_slice() {
+                        let key = parse_list::<u16>(&[onid, tsid]);
+                        if let (Some(key), Ok(version)) = (key, version.parse::<u8>()) {
+                            state.sdt_map.insert((key[0], key[1]), version % 32);
+                        }
+                    }
+                }
+                "stream" => {
+                    let value_list: Vec<&str> = value.splitn(4, ' ').collect();
+                    if let [eit, sdt, tdt, addr] = value_list.as_slice() {
+                        if let Some(cc) = parse_list::<u8>(&[eit, sdt, tdt]) {
+                            state.stream_map.insert(addr.trim().to_owned(), StreamState {
+                                eit_cc: cc[0] & 0x0F,
+                                sdt_cc: cc[1] & 0x0F,
+                                tdt_cc: cc[2] & 0x0F,
+                            });
+                        }
+                    }
+                }
+                _ => {},
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Saves state into file. File is replaced atomically
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+
+        {
+            let mut file = BufWriter::new(File::create(&tmp_path)?);
+
+            // sorted to keep file stable
+            let mut service_list: Vec<_> = self.service_map.iter().collect();
+            service_list.sort_by_key(|(k, _)| **k);
+            for ((onid, tsid, pnr), v) in service_list {
+                write!(file, "service {} {} {} {}", onid, tsid, pnr, v.present_version)?;
+                for version in &v.schedule_version {
+                    write!(file, " {}", version)?;
+                }
+                writeln!(file)?;
+            }
+
+            let mut sdt_list: Vec<_> = self.sdt_map.iter().collect();
+            sdt_list.sort_by_key(|(k, _)| **k);
+            for ((onid, tsid), version) in sdt_list {
+                writeln!(file, "sdt {} {} {}", onid, tsid, version)?;
+            }
+
+            let mut stream_list: Vec<_> = self.stream_map.iter().collect();
+            stream_list.sort_by_key(|(k, _)| k.as_str());
+            for (addr, v) in stream_list {
+                writeln!(file, "stream {} {} {} {}", v.eit_cc, v.sdt_cc, v.tdt_cc, addr)?;
+            }
+
+            file.flush()?;
+        }
+
+        fs::rename(&tmp_path, path)
+    }
+}
@@ -0,0 +1,404 @@
+use {
+    std::{
+        io::{
+            self,
+            Read,
+            Write,
+        },
+        fmt::Write as _,
+        net::{
+            TcpListener,
+            TcpStream,
+        },
+        sync::{
+            Arc,
+            Mutex,
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+        },
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+
+    crate::json,
+};
+
+
+/// Timeout to receive request and send response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum size of the request header
+const REQUEST_LIMIT: usize = 8 * 1024;
+/// Maximum number of connections handled at the same time.
+/// Other connections are closed without response
+const CLIENT_LIMIT: usize = 16;
+
+
+/// Service counters
+#[derive(Debug, Default, Clone)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub xmltv_id: String,
+    pub onid: u16,
+    pub tsid: u16,
+    pub pnr: u16,
+    /// Events of the service in XMLTV
+    pub events_loaded: usize,
+    /// Events in the EIT schedule
+    pub events_scheduled: usize,
+    /// Title of the present event. Empty if there is no present event
+    pub present: String,
+    /// Title of the following event. Empty if there is no following event
+    pub following: String,
+    pub present_version: u8,
+    pub schedule_version: u8,
+    pub sections_sent: u64,
+    pub bytes_sent: u64,
+}
+
+
+/// XMLTV source status
+#[derive(Debug, Default, Clone)]
+pub struct XmltvStatus {
+    pub path: String,
+    /// Unix time of the last successful load
+    pub load_time: u64,
+    /// Error of the last load. Empty if XMLTV loaded successfully
+    pub error: String,
+}
+
+
+/// Output stream counters
+#[derive(Debug, Default, Clone)]
+pub struct StreamStatus {
+    pub output: String,
+    /// Rate limit in kbit/s
+    pub rate_limit: usize,
+    /// Measured output rate in kbit/s
+    pub rate: f64,
+    /// Bytes sent to output including stuffing and remuxed data
+    pub bytes_sent: u64,
+    /// Bytes of the generated tables
+    pub table_bytes_sent: u64,
+    /// Output rate is not enough to keep repetition intervals
+    pub is_overload: bool,
+}
+
+
+/// Snapshot of the instance state published by the HTTP listener
+#[derive(Debug, Default)]
+pub struct Status {
+    /// Unix time of the process start
+    pub start_time: u64,
+    /// Unix time of the snapshot
+    pub update_time: u64,
+    pub service_list: Vec<ServiceStatus>,
+    pub xmltv_list: Vec<XmltvStatus>,
+    stream_list: Vec<StreamStatus>,
+    stream_time: Option<Instant>,
+}
+
+
+/// Metric name, type, help text and value getter
+type Metric<T, V> = (&'static str, &'static str, &'static str, fn(&T) -> V);
+
+
+/// Escapes label value for the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+
+/// Appends metric header
+fn push_metric(dst: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(dst, "# HELP {} {}", name, help);
+    let _ = writeln!(dst, "# TYPE {} {}", name, kind);
+}
+
+
+impl Status {
+    pub fn new(start_time: u64) -> Self {
+        Status {
+            start_time,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces streams counters. Output rate measured
+    /// with bytes sent since previous update
+    pub fn set_stream_list(&mut self, mut stream_list: Vec<StreamStatus>, now: Instant) {
+        if let Some(time) = self.stream_time {
+            let elapsed = now.saturating_duration_since(time).as_secs_f64();
+            for stream in &mut stream_list {
+                let prev = self.stream_list.iter().find(|s| s.output == stream.output);
+                if let Some(prev) = prev {
+                    if elapsed > 0.0 && stream.bytes_sent >= prev.bytes_sent {
+                        let bytes = (stream.bytes_sent - prev.bytes_sent) as f64;
+                        stream.rate = bytes * 8.0 / elapsed / 1000.0;
+                    }
+                }
+            }
+        }
+
+        self.stream_list = stream_list;
+        self.stream_time = Some(now);
+    }
+
+    /// Returns counters in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut dst = String::new();
+
+        push_metric(&mut dst, "eit_start_time_seconds", "gauge",
+            "Unix time of the process start");
+        let _ = writeln!(dst, "eit_start_time_seconds {}", self.start_time);
+
+        push_metric(&mut dst, "eit_xmltv_load_time_seconds", "gauge",
+            "Unix time of the last successful XMLTV load");
+        for x in &self.xmltv_list {
+            let _ = writeln!(dst, "eit_xmltv_load_time_seconds{{path=\"{}\"}} {}",
+                escape_label(&x.path), x.load_time);
+        }
+
+        push_metric(&mut dst, "eit_xmltv_up", "gauge",
+            "1 if last XMLTV load was successful");
+        for x in &self.xmltv_list {
+            let _ = writeln!(dst, "eit_xmltv_up{{path=\"{}\"}} {}",
+                escape_label(&x.path), x.error.is_empty() as u8);
+        }
+
+        let stream_metrics: &[Metric<StreamStatus, f64>] = &[
+            ("eit_stream_rate_kbps", "gauge",
+                "Measured output rate in kbit/s",
+                |s| s.rate),
+            ("eit_stream_rate_limit_kbps", "gauge",
+                "Output rate limit in kbit/s",
+                |s| s.rate_limit as f64),
+            ("eit_stream_bytes_sent_total", "counter",
+                "Bytes sent to output",
+                |s| s.bytes_sent as f64),
+            ("eit_stream_table_bytes_sent_total", "counter",
+                "Bytes of the generated tables sent to output",
+                |s| s.table_bytes_sent as f64),
+            ("eit_stream_overload", "gauge",
+                "1 if output rate is not enough to keep repetition intervals",
+                |s| s.is_overload as u8 as f64),
+        ];
+
+        for (name, kind, help, value) in stream_metrics {
+            push_metric(&mut dst, name, kind, help);
+            for s in &self.stream_list {
+                let _ = writeln!(dst, "{}{{output=\"{}\"}} {}",
+                    name, escape_label(&s.output), value(s));
+            }
+        }
+
+        let service_metrics: &[Metric<ServiceStatus, u64>] = &[
+            ("eit_service_events_loaded", "gauge",
+                "Events of the service in XMLTV",
+                |s| s.events_loaded as u64),
+            ("eit_service_events_scheduled", "gauge",
+                "Events in the EIT schedule",
+                |s| s.events_scheduled as u64),
+            ("eit_service_present_version", "gauge",
+                "Version of the EIT present/following table",
+                |s| u64::from(s.present_version)),
+            ("eit_service_schedule_version", "gauge",
+                "Version of the EIT schedule table",
+                |s| u64::from(s.schedule_version)),
+            ("eit_service_sections_sent_total", "counter",
+                "EIT sections sent to output",
+                |s| s.sections_sent),
+            ("eit_service_bytes_sent_total", "counter",
+                "Bytes of the EIT sent to output",
+                |s| s.bytes_sent),
+        ];
+
+        let labels = |s: &ServiceStatus| format!(
+            "onid=\"{}\",tsid=\"{}\",pnr=\"{}\",name=\"{}\"",
+            s.onid, s.tsid, s.pnr, escape_label(&s.name));
+
+        for (name, kind, help, value) in service_metrics {
+            push_metric(&mut dst, name, kind, help);
+            for s in &self.service_list {
+                let _ = writeln!(dst, "{}{{{}}} {}", name, labels(s), value(s));
+            }
+        }
+
+        push_metric(&mut dst, "eit_service_event_info", "gauge",
+            "Titles of the present and following events");
+        for s in &self.service_list {
+            for (event, title) in &[("present", &s.present), ("following", &s.following)] {
+                if ! title.is_empty() {
+                    let _ = writeln!(dst, "eit_service_event_info{{{},event=\"{}\",title=\"{}\"}} 1",
+                        labels(s), event, escape_label(title));
+                }
+            }
+        }
+
+        dst
+    }
+
+    /// Returns status in JSON format
+    pub fn json(&self) -> String {
+        let mut dst = String::new();
+
+        let _ = write!(dst, "{{\"start_time\":{},\"update_time\":{}",
+            self.start_time, self.update_time);
+
+        dst.push_str(",\"xmltv\":[");
+        for (i, x) in self.xmltv_list.iter().enumerate() {
+            if i != 0 {
+                dst.push(',');
+            }
+            dst.push('{');
+            json::push_key(&mut dst, "path");
+            json::push_str(&mut dst, &x.path);
+            let _ = write!(dst, ",\"load_time\":{},", x.load_time);
+            json::push_key(&mut dst, "error");
+            json::push_str(&mut dst, &x.error);
+            dst.push('}');
+        }
+
+        dst.push_str("],\"streams\":[");
+        for (i, s) in self.stream_list.iter().enumerate() {
+            if i != 0 {
+                dst.push(',');
+            }
+            dst.push('{');
+            json::push_key(&mut dst, "output");
+            json::push_str(&mut dst, &s.output);
+            let _ = write!(dst,
+                ",\"rate_limit\":{},\"rate\":{:.1},\"bytes_sent\":{},\"table_bytes_sent\":{},\"overload\":{}}}",
+                s.rate_limit, s.rate, s.bytes_sent, s.table_bytes_sent, s.is_overload);
+        }
+
+        dst.push_str("],\"services\":[");
+        for (i, s) in self.service_list.iter().enumerate() {
+            if i != 0 {
+                dst.push(',');
+            }
+            dst.push('{');
+            json::push_key(&mut dst, "name");
+            json::push_str(&mut dst, &s.name);
+            dst.push(',');
+            json::push_key(&mut dst, "xmltv_id");
+            json::push_str(&mut dst, &s.xmltv_id);
+            let _ = write!(dst,
+                ",\"onid\":{},\"tsid\":{},\"pnr\":{},\"events_loaded\":{},\"events_scheduled\":{},",
+                s.onid, s.tsid, s.pnr, s.events_loaded, s.events_scheduled);
+            json::push_key(&mut dst, "present");
+            json::push_str(&mut dst, &s.present);
+            dst.push(',');
+            json::push_key(&mut dst, "following");
+            json::push_str(&mut dst, &s.following);
+            let _ = write!(dst,
+                ",\"present_version\":{},\"schedule_version\":{},\"sections_sent\":{},\"bytes_sent\":{}}}",
+                s.present_version, s.schedule_version, s.sections_sent, s.bytes_sent);
+        }
+
+        dst.push_str("]}\n");
+        dst
+    }
+}
+
+
+/// Reads request header and returns method and path
+fn read_request(stream: &mut TcpStream) -> io::Result<(String, String)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    while ! buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[.. n]);
+        if buffer.len() > REQUEST_LIMIT {
+            break;
+        }
+    }
+
+    let header = String::from_utf8_lossy(&buffer);
+    let mut line = header.lines().next().unwrap_or("").split_whitespace();
+    let method = line.next().unwrap_or("").to_owned();
+    let path = line.next().unwrap_or("").to_owned();
+
+    Ok((method, path))
+}
+
+
+fn handle(mut stream: TcpStream, status: &Mutex<Status>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let (method, path) = read_request(&mut stream)?;
+    // query string is ignored
+    let path = path.split('?').next().unwrap_or("");
+
+    let (code, content_type, body) = if method != "GET" && method != "HEAD" {
+        ("405 Method Not Allowed", "text/plain", String::from("Method Not Allowed\n"))
+    } else {
+        let status = match status.lock() {
+            Ok(v) => v,
+            Err(_) => return Ok(()),
+        };
+        match path {
+            "/metrics" => ("200 OK", "text/plain; version=0.0.4", status.metrics()),
+            "/" | "/status" => ("200 OK", "application/json", status.json()),
+            _ => ("404 Not Found", "text/plain", String::from("Not Found\n")),
+        }
+    };
+
+    write!(stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code, content_type, body.len())?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+
+/// Starts HTTP listener in background.
+/// Serves `/metrics` in the Prometheus text format and `/status` in JSON.
+/// Each connection is handled in own thread, so slow client does not block others
+pub fn spawn(addr: &str, status: Arc<Mutex<Status>>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let client_count = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!("status connection failed [{}]", e);
+                    continue;
+                }
+            };
+
+            if client_count.fetch_add(1, Ordering::SeqCst) >= CLIENT_LIMIT {
+                client_count.fetch_sub(1, Ordering::SeqCst);
+                debug!("status connection dropped. too many clients");
+                continue;
+            }
+
+            let status = status.clone();
+            let client_count = client_count.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &status) {
+                    debug!("status request failed [{}]", e);
+                }
+                client_count.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use mpegts::textcode::StringDVB;
+
+
+/// Converts DVB text (EN 300 468 Annex A) into string.
+/// Character tables decoded with textcode. Control codes processed here:
+/// emphasis on/off removed, line break converted into new line.
+/// Multi-byte tables use codes from the private use area
+pub fn decode(data: &[u8]) -> String {
+    StringDVB::from(data).to_string()
+        .chars()
+        .filter(|&c| ! matches!(c, '\u{86}' | '\u{87}' | '\u{E086}' | '\u{E087}'))
+        .map(|c| if c == '\u{8A}' || c == '\u{E08A}' { '\n' } else { c })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+
+        mpegts::{
+            psi::{
+                Eit,
+                EitItem,
+                Desc4D,
+                PsiDemux,
+            },
+            textcode::{
+                self,
+                StringDVB,
+            },
+        },
+
+        crate::{
+            dump,
+            section,
+        },
+    };
+
+
+    /// Encodes text into the short event descriptor and
+    /// decodes it from the assembled EIT section
+    fn round_trip(text: &str, codepage: u8) -> String {
+        let mut item = EitItem::default();
+        item.descriptors.push(Desc4D {
+            lang: StringDVB::from_str("und", textcode::ISO6937),
+            name: StringDVB::from_str(text, codepage),
+            text: StringDVB::from_str("", codepage),
+        });
+
+        let eit = Eit {
+            table_id: 0x4E,
+            items: vec![item],
+            ..Default::default()
+        };
+
+        let psi = &eit.psi_list_assemble()[0];
+        let section = dump::Section::parse(&psi.buffer[.. section::size(psi)]).unwrap();
+        match &section.event_list[0].descriptor_list[0] {
+            dump::Descriptor::ShortEvent(_, name, _) => name.clone(),
+            d => panic!("unexpected descriptor {:?}", d),
+        }
+    }
+
+
+    #[test]
+    fn test_round_trip() {
+        let list: &[(u8, &str)] = &[
+            (0, "Café Łódź Œuvre ñ ß € ©"),
+            (1, "Café ÿ ½"),
+            (2, "Łódź Žluťoučký kůň"),
+            (3, "Ħal Ġgħajra"),
+            (4, "Ģirts Ķēniņš"),
+            (5, "Привет мир"),
+            (6, "مرحبا"),
+            (7, "Γειά σου"),
+            (8, "שלום"),
+            (9, "Şişli İğdır"),
+            (10, "Þórður Ŋ"),
+            (11, "สวัสดี"),
+            (13, "Ąžuolas Ėglė"),
+            (14, "Ŵyddfa ḃ"),
+            (15, "€ Œuvre Šárka"),
+            (21, "Привет 世界"),
+        ];
+
+        for &(codepage, text) in list {
+            assert_eq!(round_trip(text, codepage), text, "codepage {}", codepage);
+        }
+    }
+
+
+    #[test]
+    fn test_control_codes() {
+        assert_eq!(decode(b"\x86News\x87\x8Ajust"), "News\njust");
+        assert_eq!(decode(b"\x15a\xEE\x82\x8Ab"), "a\nb");
+        assert_eq!(decode(b"\x11\x04\x1F\xE0\x8A\x04\x40"), "П\nр");
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+    fs::{
+        self,
+        File,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    thread,
+    time::Duration,
+};
+
+
+#[derive(Debug, Error)]
+#[error_prefix = "XMLTV"]
+pub enum XmltvError {
+    #[error_from]
+    Io(io::Error),
+    #[error_kind("HTTP: {}", 0)]
+    Http(String),
+    #[error_kind("download failed")]
+    Thread,
+}
+
+
+pub type Result<T> = std::result::Result<T, XmltvError>;
+
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+
+/// Returns true if XMLTV should be downloaded
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+
+/// HTTP validators of the cached copy
+#[derive(Debug, Default, Clone)]
+pub struct Meta {
+    etag: String,
+    last_modified: String,
+}
+
+
+impl Meta {
+    fn load(path: &Path) -> Self {
+        let mut meta = Meta::default();
+
+        let data = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(_) => return meta,
+        };
+
+        for line in data.lines() {
+            let mut pair = line.splitn(2, ':');
+            let key = pair.next().unwrap().trim();
+            let value = pair.next().unwrap_or("").trim();
+            match key {
+                "etag" => meta.etag = value.to_owned(),
+                "last-modified" => meta.last_modified = value.to_owned(),
+                _ => {},
+            }
+        }
+
+        meta
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let data = format!("etag: {}\nlast-modified: {}\n", &self.etag, &self.last_modified);
+        fs::write(path, data)
+    }
+}
+
+
+/// Decompresses gzip or xz data. Format detected by magic bytes.
+/// Not compressed data copied as is
+fn decompress<R: BufRead, W: Write>(src: &mut R, dst: &mut W) -> io::Result<u64> {
+    let head = src.fill_buf()?;
+
+    if head.starts_with(&[0x1F, 0x8B]) {
+        io::copy(&mut flate2::bufread::MultiGzDecoder::new(src), dst)
+    } else if head.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        io::copy(&mut xz2::bufread::XzDecoder::new(src), dst)
+    } else {
+        io::copy(src, dst)
+    }
+}
+
+
+/// Downloads XMLTV into `dst`.
+/// Returns None if server responds that cached copy is not modified
+fn fetch(url: &str, dst: &Path, meta: Option<&Meta>) -> Result<Option<Meta>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+
+    let mut request = agent.get(url);
+    if let Some(meta) = meta {
+        if ! meta.etag.is_empty() {
+            request = request.set("If-None-Match", &meta.etag);
+        }
+        if ! meta.last_modified.is_empty() {
+            request = request.set("If-Modified-Since", &meta.last_modified);
+        }
+    }
+
+    let response = request.call().map_err(|e| XmltvError::Http(e.to_string()))?;
+    if response.status() == 304 {
+        return Ok(None);
+    }
+
+    let meta = Meta {
+        etag: response.header("ETag").unwrap_or("").to_owned(),
+        last_modified: response.header("Last-Modified").unwrap_or("").to_owned(),
+    };
+
+    let mut src = BufReader::new(response.into_reader());
+    let mut file = BufWriter::new(File::create(dst)?);
+    decompress(&mut src, &mut file)?;
+    file.flush()?;
+
+    Ok(Some(meta))
+}
+
+
+/// Maximum length of the readable part of the cache file name
+const CACHE_PREFIX_SIZE: usize = 64;
+
+
+/// FNV-1a 64-bit hash. Stable between runs and builds
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+
+/// Returns name of the cached copy: readable prefix from URL and hash of the full URL
+fn cache_name(url: &str) -> String {
+    let prefix: String = url.splitn(2, "://").last().unwrap_or("")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(CACHE_PREFIX_SIZE)
+        .collect();
+
+    format!("{}-{:016x}.xml", prefix, fnv1a(url.as_bytes()))
+}
+
+
+/// XMLTV source available over HTTP(S).
+/// Last downloaded copy kept in the cache directory and
+/// used if server is not available
+#[derive(Debug)]
+pub struct Remote {
+    url: String,
+    /// Path to the cached copy
+    path: PathBuf,
+    meta: Meta,
+    task: Option<thread::JoinHandle<Result<Option<Meta>>>>,
+}
+
+
+impl Remote {
+    pub fn new(url: &str, cache_dir: &Path) -> Self {
+        let path = cache_dir.join(cache_name(url));
+        let meta = Meta::load(&path.with_extension("meta"));
+
+        Remote {
+            url: url.to_owned(),
+            path,
+            meta,
+            task: None,
+        }
+    }
+
+    /// Returns path to the cached copy
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if cached copy is available
+    #[inline]
+    pub fn is_cached(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Returns path to the downloaded copy
+    #[inline]
+    pub fn tmp_path(&self) -> PathBuf {
+        self.path.with_extension("tmp")
+    }
+
+    /// Downloads XMLTV. Returns None if cached copy is not modified
+    pub fn fetch(&self) -> Result<Option<Meta>> {
+        let meta = if self.is_cached() { Some(&self.meta) } else { None };
+        fetch(&self.url, &self.tmp_path(), meta)
+    }
+
+    /// Starts download in background
+    pub fn spawn(&mut self) {
+        if self.task.is_some() {
+            return;
+        }
+
+        let url = self.url.clone();
+        let dst = self.tmp_path();
+        let meta = if self.is_cached() { Some(self.meta.clone()) } else { None };
+
+        self.task = Some(thread::spawn(move || {
+            fetch(&url, &dst, meta.as_ref())
+        }));
+    }
+
+    /// Returns result of the background download if completed
+    pub fn poll(&mut self) -> Option<Result<Option<Meta>>> {
+        if ! self.task.as_ref()?.is_finished() {
+            return None;
+        }
+
+        let task = self.task.take()?;
+        Some(task.join().unwrap_or(Err(XmltvError::Thread)))
+    }
+
+    /// Replaces cached copy with the downloaded copy
+    pub fn commit(&mut self, meta: Meta) -> io::Result<()> {
+        fs::rename(self.tmp_path(), &self.path)?;
+        meta.save(&self.path.with_extension("meta"))?;
+        self.meta = meta;
+        Ok(())
+    }
+
+    /// Removes downloaded copy if it is invalid
+    pub fn discard(&self) {
+        let _ = fs::remove_file(self.tmp_path());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::Read,
+            net::TcpListener,
+            sync::mpsc,
+        },
+    };
+
+    const XMLTV: &str = "<?xml version=\"1.0\"?><tv></tv>\n";
+    const ETAG: &str = "\"v1\"";
+    const LAST_MODIFIED: &str = "Mon, 05 Oct 2026 10:00:00 GMT";
+
+
+    /// Starts HTTP server for the list of responses.
+    /// Returns URL and channel with received request headers
+    fn serve(response_list: Vec<Vec<u8>>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tv.xml", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for response in response_list {
+                let (mut stream, _) = listener.accept().unwrap();
+
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while ! request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buffer).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[.. n]);
+                }
+
+                tx.send(String::from_utf8_lossy(&request).to_lowercase()).unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+            status, body.len(), headers).into_bytes();
+        data.extend_from_slice(body);
+        data
+    }
+
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("eit-stream-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+
+    #[test]
+    fn test_fetch_validators() {
+        let headers = format!("ETag: {}\r\nLast-Modified: {}\r\n", ETAG, LAST_MODIFIED);
+        let (url, rx) = serve(vec![
+            response("200 OK", &headers, XMLTV.as_bytes()),
+            response("304 Not Modified", "", b""),
+        ]);
+        let dir = cache_dir("validators");
+
+        let mut remote = Remote::new(&url, &dir);
+        assert!(! remote.is_cached());
+        let meta = remote.fetch().unwrap().unwrap();
+        assert!(! rx.recv().unwrap().contains("if-none-match"));
+        remote.commit(meta).unwrap();
+        assert_eq!(fs::read_to_string(remote.path()).unwrap(), XMLTV);
+
+        // validators loaded from the cache on restart
+        let remote = Remote::new(&url, &dir);
+        assert!(remote.fetch().unwrap().is_none());
+        let request = rx.recv().unwrap();
+        assert!(request.contains(&format!("if-none-match: {}", ETAG).to_lowercase()));
+        assert!(request.contains(&format!("if-modified-since: {}", LAST_MODIFIED).to_lowercase()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+
+    #[test]
+    fn test_fetch_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(XMLTV.as_bytes()).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let (url, _rx) = serve(vec![ response("200 OK", "", &body) ]);
+        let dir = cache_dir("gzip");
+
+        let remote = Remote::new(&url, &dir);
+        assert!(remote.fetch().unwrap().is_some());
+        assert_eq!(fs::read_to_string(remote.tmp_path()).unwrap(), XMLTV);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+
+    #[test]
+    fn test_fetch_error_keeps_cache() {
+        let (url, _rx) = serve(vec![
+            response("200 OK", &format!("ETag: {}\r\n", ETAG), XMLTV.as_bytes()),
+            response("500 Internal Server Error", "", b""),
+        ]);
+        let dir = cache_dir("error");
+
+        let mut remote = Remote::new(&url, &dir);
+        let meta = remote.fetch().unwrap().unwrap();
+        remote.commit(meta).unwrap();
+
+        let mut remote = Remote::new(&url, &dir);
+        remote.spawn();
+        let result = loop {
+            if let Some(v) = remote.poll() {
+                break v;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        assert!(matches!(result, Err(XmltvError::Http(_))));
+        assert!(remote.is_cached());
+        assert_eq!(fs::read_to_string(remote.path()).unwrap(), XMLTV);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+
+    #[test]
+    fn test_cache_name() {
+        let name = cache_name("https://example.com/epg.xml.gz?id=1");
+        assert!(name.starts_with("example_com_epg_xml_gz_id_1-"));
+        assert!(name.ends_with(".xml"));
+
+        // same readable prefix
+        assert_ne!(cache_name("http://example.com/a_b"), cache_name("http://example.com/a?b"));
+        assert_ne!(cache_name("http://example.com/a"), cache_name("https://example.com/a"));
+        assert_eq!(cache_name("http://example.com/a"), cache_name("http://example.com/a"));
+    }
+}